@@ -0,0 +1,84 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use sha2::{Digest, Sha256};
+
+use gmh::provider::{GenerateOptions, ProviderKind};
+
+use crate::config;
+
+// 取消后重新运行时不用再请求一次
+const TTL: Duration = Duration::from_secs(60 * 60);
+
+// $XDG_CACHE_HOME/gmh, 没有设置时用 ~/.cache/gmh
+fn cache_dir() -> Option<PathBuf> {
+    let base = config::env_value("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| config::home_dir().map(|home| home.join(".cache")))?;
+    Some(base.join("gmh"))
+}
+
+// 影响模型回复的参数都算进 key, 换了 api_base、temperature 或 seed 时重新请求
+pub fn key(provider: ProviderKind, api_base: Option<&str>, opts: &GenerateOptions, diff: &str) -> String {
+    let mut hasher = Sha256::new();
+    let provider = format!("{:?}", provider);
+    let sampling = format!("{:?} {:?} {:?}", opts.temperature, opts.max_tokens, opts.seed);
+    let fields = [provider.as_str(), api_base.unwrap_or_default(), &opts.model, &opts.system_prompt, &sampling]
+        .into_iter()
+        .chain(opts.context.iter().map(String::as_str))
+        .chain([diff]);
+    // 用 \0 分隔, 避免不同字段拼接后碰撞
+    for part in fields {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// 过期或读取失败都当作没有命中
+pub fn get(key: &str) -> Option<String> {
+    let path = cache_dir()?.join(key);
+    let modified = fs::metadata(&path).ok()?.modified().ok()?;
+    let age = SystemTime::now().duration_since(modified).unwrap_or_default();
+    if age > TTL {
+        let _ = fs::remove_file(&path);
+        return None;
+    }
+    fs::read_to_string(path).ok().filter(|message| !message.trim().is_empty())
+}
+
+// 缓存只是优化, 写入失败不影响提交
+pub fn put(key: &str, message: &str) {
+    let Some(dir) = cache_dir() else {
+        return;
+    };
+    if fs::create_dir_all(&dir).is_ok() {
+        let _ = fs::write(dir.join(key), message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_changes_with_the_endpoint_and_sampling_settings() {
+        let opts = GenerateOptions::default();
+        let base = key(ProviderKind::Deepseek, None, &opts, "diff");
+        assert_eq!(key(ProviderKind::Deepseek, None, &opts, "diff"), base);
+        assert_ne!(key(ProviderKind::Deepseek, Some("http://localhost:8080"), &opts, "diff"), base);
+        let changed = [
+            GenerateOptions { temperature: Some(1.5), ..GenerateOptions::default() },
+            GenerateOptions { max_tokens: Some(100), ..GenerateOptions::default() },
+            GenerateOptions { seed: Some(7), ..GenerateOptions::default() },
+        ];
+        for opts in &changed {
+            assert_ne!(key(ProviderKind::Deepseek, None, opts, "diff"), base);
+        }
+    }
+}
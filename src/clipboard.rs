@@ -0,0 +1,46 @@
+use std::env;
+use std::io::{ErrorKind, Write};
+use std::process::{Command, Stdio};
+
+use gmh::GmhError;
+
+// 各平台自带或常见的剪贴板命令, 按顺序尝试, 找不到的跳过
+fn commands() -> Vec<(&'static str, &'static [&'static str])> {
+    if cfg!(target_os = "macos") {
+        vec![("pbcopy", &[])]
+    } else if cfg!(windows) {
+        vec![("clip", &[])]
+    } else if env::var_os("WAYLAND_DISPLAY").is_some() {
+        vec![("wl-copy", &[]), ("xclip", &["-selection", "clipboard"]), ("xsel", &["--clipboard", "--input"])]
+    } else {
+        vec![("xclip", &["-selection", "clipboard"]), ("xsel", &["--clipboard", "--input"]), ("wl-copy", &[])]
+    }
+}
+
+// 通过 stdin 交给剪贴板命令, 返回用的是哪个命令
+pub fn copy(text: &str) -> Result<&'static str, GmhError> {
+    for (program, args) in commands() {
+        let mut child = match Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => return Err(GmhError::Clipboard(format!("cannot run {}: {}", program, err))),
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(GmhError::Clipboard(format!("{} exited with {}\n{}", program, output.status, stderr)));
+        }
+        return Ok(program);
+    }
+    let tried: Vec<&str> = commands().iter().map(|(program, _)| *program).collect();
+    Err(GmhError::Clipboard(format!("no clipboard command found, install one of: {}", tried.join(", "))))
+}
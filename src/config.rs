@@ -0,0 +1,435 @@
+use clap::ValueEnum;
+use regex::Regex;
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+
+use gmh::git::DiffAlgorithm;
+use gmh::message::{validate_scope, MessageFormat, DEFAULT_ISSUE_PATTERN};
+use gmh::provider::{ProviderKind, DEFAULT_MAX_RETRIES, DEFAULT_MAX_WAIT, DEFAULT_TEMPERATURE, DEFAULT_TIMEOUT};
+use gmh::GmhError;
+
+use crate::Cli;
+
+pub const REPO_CONFIG_FILE: &str = ".gmh.toml";
+
+pub const DEFAULT_MAX_DIFF_TOKENS: usize = 16000;
+// 比这更短的消息 (例如 "Update") 在 --retry-empty 时会重新生成
+pub const DEFAULT_MIN_MESSAGE_LENGTH: usize = 10;
+// 再多的上下文对理解改动没有帮助, 只会占满 --max-diff-tokens
+pub const MAX_CONTEXT_LINES: u32 = 100;
+
+// ~/.config/gmh/config.toml 和仓库里的 .gmh.toml, 字段和命令行参数一一对应
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Config {
+    pub provider: Option<ProviderKind>,
+    pub model: Option<String>,
+    pub model_fallback: Option<String>,
+    pub api_base: Option<String>,
+    pub stream: Option<bool>,
+    pub max_retries: Option<u32>,
+    pub max_wait: Option<u64>,
+    pub timeout: Option<u64>,
+    pub conventional: Option<bool>,
+    pub scope: Option<String>,
+    pub max_diff_tokens: Option<usize>,
+    pub diff_algorithm: Option<DiffAlgorithm>,
+    pub context_lines: Option<u32>,
+    pub prefix: Option<String>,
+    pub suffix: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub prompt_file: Option<PathBuf>,
+    pub lang: Option<String>,
+    pub format: Option<MessageFormat>,
+    pub wrap: Option<bool>,
+    pub commit_template: Option<bool>,
+    pub max_subject_length: Option<usize>,
+    pub retry_empty: Option<bool>,
+    pub max_words: Option<usize>,
+    pub min_message_length: Option<usize>,
+    pub gitmoji: Option<bool>,
+    pub emoji: Option<bool>,
+    pub offline_fallback: Option<bool>,
+    pub default_yes: Option<bool>,
+    pub confirm_prompt: Option<String>,
+    pub postprocess: Option<String>,
+    // 额外的 gitignore 规则, 和 .gmhignore 一起生效
+    pub ignore: Vec<String>,
+    // 从分支名里取 issue 编号的正则, 空字符串表示关闭
+    pub issue_pattern: Option<String>,
+}
+
+impl Config {
+    // self 优先, 没有设置的字段用 fallback 的; ignore 规则合并
+    pub fn or(self, fallback: Config) -> Config {
+        Config {
+            provider: self.provider.or(fallback.provider),
+            model: self.model.or(fallback.model),
+            model_fallback: self.model_fallback.or(fallback.model_fallback),
+            api_base: self.api_base.or(fallback.api_base),
+            stream: self.stream.or(fallback.stream),
+            max_retries: self.max_retries.or(fallback.max_retries),
+            max_wait: self.max_wait.or(fallback.max_wait),
+            timeout: self.timeout.or(fallback.timeout),
+            conventional: self.conventional.or(fallback.conventional),
+            scope: self.scope.or(fallback.scope),
+            max_diff_tokens: self.max_diff_tokens.or(fallback.max_diff_tokens),
+            diff_algorithm: self.diff_algorithm.or(fallback.diff_algorithm),
+            context_lines: self.context_lines.or(fallback.context_lines),
+            prefix: self.prefix.or(fallback.prefix),
+            suffix: self.suffix.or(fallback.suffix),
+            temperature: self.temperature.or(fallback.temperature),
+            max_tokens: self.max_tokens.or(fallback.max_tokens),
+            prompt_file: self.prompt_file.or(fallback.prompt_file),
+            lang: self.lang.or(fallback.lang),
+            format: self.format.or(fallback.format),
+            wrap: self.wrap.or(fallback.wrap),
+            commit_template: self.commit_template.or(fallback.commit_template),
+            max_subject_length: self.max_subject_length.or(fallback.max_subject_length),
+            retry_empty: self.retry_empty.or(fallback.retry_empty),
+            max_words: self.max_words.or(fallback.max_words),
+            min_message_length: self.min_message_length.or(fallback.min_message_length),
+            gitmoji: self.gitmoji.or(fallback.gitmoji),
+            emoji: self.emoji.or(fallback.emoji),
+            offline_fallback: self.offline_fallback.or(fallback.offline_fallback),
+            default_yes: self.default_yes.or(fallback.default_yes),
+            confirm_prompt: self.confirm_prompt.or(fallback.confirm_prompt),
+            postprocess: self.postprocess.or(fallback.postprocess),
+            ignore: [fallback.ignore, self.ignore].concat(),
+            issue_pattern: self.issue_pattern.or(fallback.issue_pattern),
+        }
+    }
+}
+
+// 最终生效的配置
+#[derive(Debug)]
+pub struct Settings {
+    pub provider: ProviderKind,
+    pub model: String,
+    // 消息没有通过检查 (太短、不是 Conventional Commit) 时换这个模型重试一次
+    pub model_fallback: Option<String>,
+    pub api_base: Option<String>,
+    pub stream: bool,
+    pub max_retries: u32,
+    pub max_wait: u64,
+    pub timeout: u64,
+    pub conventional: bool,
+    // 强制使用的 Conventional Commits scope, 设置时隐含 conventional
+    pub scope: Option<String>,
+    pub max_diff_tokens: usize,
+    pub diff_algorithm: Option<DiffAlgorithm>,
+    pub context_lines: Option<u32>,
+    pub prefix: Option<String>,
+    pub suffix: Option<String>,
+    pub temperature: f32,
+    pub max_tokens: Option<u32>,
+    // 自定义的 system prompt, None 时使用内置的
+    pub system_prompt: Option<String>,
+    // 提交消息使用的语言, None 时保持英文
+    pub lang: Option<String>,
+    pub format: MessageFormat,
+    pub wrap: bool,
+    pub commit_template: bool,
+    // None 时只在超过 50 个字符时警告
+    pub max_subject_length: Option<usize>,
+    pub retry_empty: bool,
+    pub max_words: Option<usize>,
+    pub min_message_length: usize,
+    pub gitmoji: bool,
+    // gitmoji shortcode 换成 emoji
+    pub emoji: bool,
+    // 连不上 API 或没有 key 时用 diff stat 拼一个消息
+    pub offline_fallback: bool,
+    // 确认提交时直接回车算 yes
+    pub default_yes: bool,
+    // None 时用内置的提示
+    pub confirm_prompt: Option<String>,
+    // 提交前消息经过这个 shell 命令处理
+    pub postprocess: Option<String>,
+    pub ignore: Vec<String>,
+    // None 时不从分支名里取 issue 编号
+    pub issue_pattern: Option<Regex>,
+}
+
+// $XDG_CONFIG_HOME/gmh/config.toml, 没有设置时用 ~/.config/gmh/config.toml
+pub fn config_path() -> Option<PathBuf> {
+    let base = env_value("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home_dir().map(|home| home.join(".config")))?;
+    Some(base.join("gmh").join("config.toml"))
+}
+
+// API key 放在配置文件旁边的 .env 里, 不和可能被分享的 config.toml 混在一起
+pub fn env_path() -> Option<PathBuf> {
+    config_path().map(|path| path.with_file_name(".env"))
+}
+
+// 优先级从低到高: 全局的 .env, .env, .env.local, 然后是按顺序给出的 --env-file.
+// dotenv 不覆盖已经设置的变量, 所以从优先级最高的开始加载, 真正的环境变量始终优先
+pub fn load_env_files(env_files: &[PathBuf]) -> Result<(), GmhError> {
+    for path in env_files.iter().rev() {
+        dotenv::from_path(path)
+            .map_err(|e| GmhError::Config(format!("cannot load env file {}: {}", path.display(), e)))?;
+    }
+    dotenv::from_filename(".env.local").ok();
+    dotenv::dotenv().ok();
+    if let Some(path) = env_path() {
+        dotenv::from_path(path).ok();
+    }
+    Ok(())
+}
+
+pub fn home_dir() -> Option<PathBuf> {
+    env_value("HOME").or_else(|| env_value("USERPROFILE")).map(PathBuf::from)
+}
+
+// 文件不存在时当作空配置
+// 全局配置优先于仓库里的 .gmh.toml
+pub fn load_config(repo_root: Option<&Path>) -> Result<Config, GmhError> {
+    let global = match config_path() {
+        Some(path) => load_from(&path)?,
+        None => Config::default(),
+    };
+    let repo = match repo_root {
+        Some(root) => load_repo_config(root)?,
+        None => Config::default(),
+    };
+    Ok(global.or(repo))
+}
+
+// 仓库里的配置会随代码一起被 clone 下来, 不允许改 api_base 和 postprocess (会通过 shell 执行),
+// prompt_file 只能指向仓库内
+pub fn load_repo_config(root: &Path) -> Result<Config, GmhError> {
+    let path = root.join(REPO_CONFIG_FILE);
+    let mut config = load_from(&path)?;
+    if config.api_base.take().is_some() {
+        eprintln!(
+            "Warning: ignoring api_base in {}; set it in the global config or GMH_API_BASE.",
+            path.display()
+        );
+    }
+    if config.postprocess.take().is_some() {
+        eprintln!(
+            "Warning: ignoring postprocess in {}; set it in the global config or pass --postprocess.",
+            path.display()
+        );
+    }
+    if let Some(prompt_file) = config.prompt_file.take() {
+        if prompt_file.is_absolute() || prompt_file.components().any(|c| c == Component::ParentDir) {
+            return Err(GmhError::Config(format!(
+                "{}: prompt_file must be a relative path inside the repository",
+                path.display()
+            )));
+        }
+        config.prompt_file = Some(root.join(prompt_file));
+    }
+    Ok(config)
+}
+
+pub fn load_from(path: &Path) -> Result<Config, GmhError> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Config::default()),
+        Err(source) => {
+            return Err(GmhError::File {
+                path: path.display().to_string(),
+                source,
+            })
+        }
+    };
+    toml::from_str(&text).map_err(|e| GmhError::Config(format!("{}: {}", path.display(), e)))
+}
+
+pub fn env_value(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|v| !v.is_empty())
+}
+
+// 优先级: 命令行参数 > 环境变量 > 配置文件
+pub fn resolve_setting(flag: Option<String>, env_value: Option<String>, file_value: Option<String>) -> Option<String> {
+    flag.filter(|v| !v.is_empty())
+        .or_else(|| env_value.filter(|v| !v.is_empty()))
+        .or_else(|| file_value.filter(|v| !v.is_empty()))
+}
+
+pub fn resolve_model(flag: Option<String>, env_value: Option<String>, file_value: Option<String>, default: &str) -> String {
+    resolve_setting(flag, env_value, file_value).unwrap_or_else(|| default.to_string())
+}
+
+// 优先级: --stream / --no-stream > GMH_STREAM > 配置文件, 默认不流式输出
+pub fn resolve_stream(flag: Option<bool>, env_value: Option<String>, file_value: Option<bool>) -> Result<bool, GmhError> {
+    if let Some(stream) = flag {
+        return Ok(stream);
+    }
+    if let Some(value) = env_value {
+        return match value.to_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Ok(true),
+            "0" | "false" | "no" | "off" => Ok(false),
+            _ => Err(GmhError::Config(format!("invalid GMH_STREAM '{}', expected true or false", value))),
+        };
+    }
+    Ok(file_value.unwrap_or(false))
+}
+
+pub fn resolve_provider(
+    flag: Option<ProviderKind>,
+    env_value: Option<String>,
+    file_value: Option<ProviderKind>,
+) -> Result<ProviderKind, GmhError> {
+    if let Some(kind) = flag {
+        return Ok(kind);
+    }
+    if let Some(value) = env_value.filter(|v| !v.is_empty()) {
+        return ProviderKind::from_str(&value, true)
+            .map_err(|_| GmhError::Config(format!("invalid GMH_PROVIDER '{}'", value)));
+    }
+    Ok(file_value.unwrap_or(ProviderKind::Deepseek))
+}
+
+pub fn parse_temperature(value: &str) -> Result<f32, String> {
+    let temperature: f32 = value.parse().map_err(|_| format!("'{}' is not a number", value))?;
+    validate_temperature(temperature)
+}
+
+// 和 --context-lines 的取值范围一致, 配置文件里的值也要检查
+fn validate_context_lines(lines: Option<u32>) -> Result<Option<u32>, GmhError> {
+    match lines {
+        Some(lines) if lines > MAX_CONTEXT_LINES => Err(GmhError::Config(format!(
+            "context_lines must be between 0 and {}, got {}",
+            MAX_CONTEXT_LINES, lines
+        ))),
+        lines => Ok(lines),
+    }
+}
+
+fn validate_temperature(temperature: f32) -> Result<f32, String> {
+    if (0.0..=2.0).contains(&temperature) {
+        Ok(temperature)
+    } else {
+        Err(format!("temperature must be between 0.0 and 2.0, got {}", temperature))
+    }
+}
+
+// 指定了文件就必须能读到, 不会悄悄退回默认 prompt
+fn read_prompt_file(path: &Path) -> Result<String, GmhError> {
+    let text = fs::read_to_string(path).map_err(|source| GmhError::File {
+        path: path.display().to_string(),
+        source,
+    })?;
+    if text.trim().is_empty() {
+        return Err(GmhError::Config(format!("prompt file {} is empty", path.display())));
+    }
+    Ok(text.trim().to_string())
+}
+
+// 优先级: --prompt-file > GMH_SYSTEM_PROMPT > 配置文件里的 prompt_file
+fn resolve_system_prompt(flag: Option<&Path>, env_value: Option<String>, file_value: Option<&Path>) -> Result<Option<String>, GmhError> {
+    if let Some(path) = flag {
+        return read_prompt_file(path).map(Some);
+    }
+    if let Some(prompt) = env_value {
+        return Ok(Some(prompt));
+    }
+    file_value.map(read_prompt_file).transpose()
+}
+
+fn resolve_issue_pattern(pattern: Option<&str>) -> Result<Option<Regex>, GmhError> {
+    match pattern.unwrap_or(DEFAULT_ISSUE_PATTERN) {
+        "" => Ok(None),
+        pattern => Regex::new(pattern)
+            .map(Some)
+            .map_err(|e| GmhError::Config(format!("invalid issue_pattern: {}", e))),
+    }
+}
+
+impl Settings {
+    pub fn resolve(cli: &Cli, config: Config) -> Result<Settings, GmhError> {
+        let provider = resolve_provider(cli.provider, env_value("GMH_PROVIDER"), config.provider)?;
+        let model = resolve_model(
+            cli.model.clone(),
+            env_value("GMH_MODEL"),
+            config.model,
+            provider.default_model(),
+        );
+
+        Ok(Settings {
+            provider,
+            model,
+            model_fallback: resolve_setting(cli.model_fallback.clone(), None, config.model_fallback),
+            api_base: resolve_setting(cli.api_base.clone(), env_value("GMH_API_BASE"), config.api_base),
+            stream: resolve_stream(
+                (cli.stream || cli.no_stream).then_some(cli.stream),
+                env_value("GMH_STREAM"),
+                config.stream,
+            )?,
+            max_retries: cli.max_retries.or(config.max_retries).unwrap_or(DEFAULT_MAX_RETRIES),
+            max_wait: cli.max_wait.or(config.max_wait).unwrap_or(DEFAULT_MAX_WAIT),
+            timeout: cli.timeout.or(config.timeout).unwrap_or(DEFAULT_TIMEOUT),
+            conventional: cli.conventional
+                || cli.scope.is_some()
+                || config.scope.is_some()
+                || config.conventional.unwrap_or(false),
+            scope: resolve_setting(cli.scope.clone(), None, config.scope)
+                .map(|scope| validate_scope(&scope))
+                .transpose()
+                .map_err(GmhError::Config)?,
+            wrap: cli.wrap || config.wrap.unwrap_or(false),
+            commit_template: !cli.no_commit_template && config.commit_template.unwrap_or(true),
+            max_subject_length: cli.max_subject_length.or(config.max_subject_length),
+            retry_empty: cli.retry_empty || config.retry_empty.unwrap_or(false),
+            max_words: cli.max_words.map(|n| n as usize).or(config.max_words),
+            min_message_length: cli
+                .min_message_length
+                .or(config.min_message_length)
+                .unwrap_or(DEFAULT_MIN_MESSAGE_LENGTH),
+            gitmoji: cli.gitmoji || config.gitmoji.unwrap_or(false),
+            emoji: cli.emoji || config.emoji.unwrap_or(false),
+            offline_fallback: cli.offline_fallback || config.offline_fallback.unwrap_or(false),
+            default_yes: cli.default_yes || config.default_yes.unwrap_or(false),
+            confirm_prompt: resolve_setting(cli.confirm_prompt.clone(), None, config.confirm_prompt),
+            postprocess: resolve_setting(cli.postprocess.clone(), None, config.postprocess),
+            ignore: config.ignore,
+            issue_pattern: resolve_issue_pattern(config.issue_pattern.as_deref())?,
+            max_diff_tokens: cli
+                .max_diff_tokens
+                .or(config.max_diff_tokens)
+                .unwrap_or(DEFAULT_MAX_DIFF_TOKENS),
+            diff_algorithm: cli.diff_algorithm.or(config.diff_algorithm),
+            context_lines: validate_context_lines(cli.context_lines.or(config.context_lines))?,
+            prefix: resolve_setting(cli.prefix.clone(), env_value("GMH_PREFIX"), config.prefix),
+            suffix: resolve_setting(cli.suffix.clone(), env_value("GMH_SUFFIX"), config.suffix),
+            temperature: validate_temperature(
+                cli.temperature.or(config.temperature).unwrap_or(DEFAULT_TEMPERATURE),
+            )
+            .map_err(GmhError::Config)?,
+            max_tokens: cli.max_tokens.or(config.max_tokens),
+            system_prompt: resolve_system_prompt(
+                cli.prompt_file.as_deref(),
+                env_value("GMH_SYSTEM_PROMPT"),
+                config.prompt_file.as_deref(),
+            )?,
+            lang: resolve_setting(cli.lang.clone(), env_value("GMH_LANG"), config.lang),
+            format: cli.format.or(config.format).unwrap_or_default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn model_comes_from_the_flag_then_env_then_file_then_default() {
+        let some = |value: &str| Some(value.to_string());
+        let resolve = |flag, env, file| resolve_model(flag, env, file, "deepseek-chat");
+        assert_eq!(resolve(some("flag"), some("env"), some("file")), "flag");
+        assert_eq!(resolve(None, some("env"), some("file")), "env");
+        assert_eq!(resolve(None, None, some("file")), "file");
+        assert_eq!(resolve(None, None, None), "deepseek-chat");
+        // 空字符串当作没有设置
+        assert_eq!(resolve(some(""), some(""), None), "deepseek-chat");
+    }
+}
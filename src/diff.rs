@@ -0,0 +1,418 @@
+use std::path::Path;
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use crate::error::GmhError;
+
+// 粗略估算: 平均 4 个字符算一个 token. 还没有接入 tokenizer (tiktoken-rs), --count-tokens 和截断都用这个
+const CHARS_PER_TOKEN: usize = 4;
+
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+// chat 格式里每条消息的 role 和分隔符大约占的 token
+const TOKENS_PER_MESSAGE: usize = 4;
+
+// 整个请求里所有消息的 token 估算, 和 estimate_tokens 一样是粗略值
+pub fn estimate_message_tokens<'a>(contents: impl IntoIterator<Item = &'a str>) -> usize {
+    contents
+        .into_iter()
+        .map(|content| estimate_tokens(content) + TOKENS_PER_MESSAGE)
+        .sum()
+}
+
+// 按 `diff --git` 把整个 diff 拆成每个文件一段
+pub fn split_files(diff: &str) -> Vec<&str> {
+    let mut sections = Vec::new();
+    let mut start = 0;
+    for (offset, _) in diff.match_indices("diff --git ") {
+        if offset != 0 && diff.as_bytes()[offset - 1] != b'\n' {
+            continue;
+        }
+        if offset > start {
+            sections.push(&diff[start..offset]);
+        }
+        start = offset;
+    }
+    if start < diff.len() {
+        sections.push(&diff[start..]);
+    }
+    sections
+}
+
+// lockfile 之类的改动只会浪费 token
+const DEFAULT_IGNORES: &[&str] = &[
+    "Cargo.lock",
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "bun.lockb",
+    "composer.lock",
+    "Gemfile.lock",
+    "poetry.lock",
+    "Pipfile.lock",
+    "uv.lock",
+    "go.sum",
+];
+
+// 仓库根目录下的 .gmhignore 加上配置里的 patterns (gitignore 语法),
+// defaults 为 true 时加上内置的 lockfile 规则
+pub fn load_ignore(root: &Path, patterns: &[String], defaults: bool) -> Result<Gitignore, GmhError> {
+    let mut builder = GitignoreBuilder::new(root);
+    let defaults = if defaults { DEFAULT_IGNORES } else { &[] };
+    for pattern in defaults.iter().copied().chain(patterns.iter().map(String::as_str)) {
+        builder
+            .add_line(None, pattern)
+            .map_err(|e| GmhError::Config(e.to_string()))?;
+    }
+    let path = root.join(".gmhignore");
+    if path.is_file() {
+        if let Some(err) = builder.add(&path) {
+            return Err(GmhError::Config(format!("{}: {}", path.display(), err)));
+        }
+    }
+    builder.build().map_err(|e| GmhError::Config(e.to_string()))
+}
+
+// 优先用 +++ b/ 的新路径 (改名后的文件算新名字), 删除的文件新路径是 /dev/null, 用 --- a/ 的旧路径.
+// 二进制或只改权限的文件从 `diff --git a/x b/x` 里取
+pub(crate) fn file_path(section: &str) -> Option<&str> {
+    let mut old = None;
+    for line in section.lines() {
+        if line.starts_with("@@") {
+            break;
+        }
+        if let Some(path) = line.strip_prefix("+++ b/") {
+            return Some(path.trim_end());
+        }
+        if let Some(path) = line.strip_prefix("--- a/") {
+            old = Some(path.trim_end());
+        }
+    }
+    if old.is_some() {
+        return old;
+    }
+    let header = section.lines().next()?.strip_prefix("diff --git a/")?;
+    header.rsplit_once(" b/").map(|(_, path)| path)
+}
+
+// diff 里出现的文件, 按出现顺序
+pub fn files(diff: &str) -> Vec<&str> {
+    split_files(diff).into_iter().filter_map(file_path).collect()
+}
+
+// 去掉匹配 ignore 规则的文件, 最后用一行说明被排除的文件, 不至于完全丢失上下文.
+// 返回过滤后的 diff 以及被排除的文件
+pub fn exclude_files(diff: &str, ignore: &Gitignore) -> (String, Vec<String>) {
+    let mut out = String::with_capacity(diff.len());
+    let mut excluded = Vec::new();
+    for section in split_files(diff) {
+        match file_path(section) {
+            Some(path) if ignore.matched_path_or_any_parents(path, false).is_ignore() => {
+                excluded.push(path.to_string());
+            }
+            _ => out.push_str(section),
+        }
+    }
+    if !excluded.is_empty() {
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+        }
+        out.push_str(&format!("(changes to {} omitted)\n", excluded.join(", ")));
+    }
+    (out, excluded)
+}
+
+// 解码失败的字符超过 1% 时当作二进制内容
+const MAX_REPLACEMENT_RATIO: f64 = 0.01;
+
+fn is_binary(section: &str) -> bool {
+    let marker = section.lines().any(|line| {
+        (line.starts_with("Binary files ") && line.ends_with(" differ")) || line == "GIT binary patch"
+    });
+    if marker {
+        return true;
+    }
+    let total = section.chars().count();
+    let replaced = section.chars().filter(|&c| c == char::REPLACEMENT_CHARACTER).count();
+    total > 0 && replaced as f64 / total as f64 > MAX_REPLACEMENT_RATIO
+}
+
+// 二进制文件 (或 lossy 解码后大量乱码的文件) 只保留一行说明, 不把乱码发给模型
+pub fn summarize_binary(diff: &str) -> String {
+    let mut out = String::with_capacity(diff.len());
+    for section in split_files(diff) {
+        if !is_binary(section) {
+            out.push_str(section);
+            continue;
+        }
+        match file_path(section) {
+            Some(path) => out.push_str(&format!("Binary file changed: {}\n", path)),
+            None => out.push_str("Binary file changed\n"),
+        }
+    }
+    out
+}
+
+// 超过 max_tokens 时按文件平均分配预算, 只截断内容行,
+// 文件头和 @@ hunk 头全部保留, 被省略的部分用 `... (N lines omitted)` 标记.
+// 返回截断后的 diff 以及是否发生了截断
+pub fn truncate_diff(diff: &str, max_tokens: usize) -> (String, bool) {
+    if estimate_tokens(diff) <= max_tokens {
+        return (diff.to_string(), false);
+    }
+
+    let files = split_files(diff);
+    let budget = max_tokens * CHARS_PER_TOKEN / files.len().max(1);
+    let mut out = String::with_capacity(max_tokens * CHARS_PER_TOKEN);
+
+    for file in files {
+        let mut in_header = true;
+        let mut used = 0;
+        let mut omitted = 0;
+
+        for line in file.lines() {
+            if line.starts_with("@@") {
+                in_header = false;
+                flush_omitted(&mut out, &mut omitted);
+                out.push_str(line);
+                out.push('\n');
+            } else if in_header {
+                out.push_str(line);
+                out.push('\n');
+            } else if omitted == 0 && used + line.len() < budget {
+                used += line.len() + 1;
+                out.push_str(line);
+                out.push('\n');
+            } else {
+                omitted += 1;
+            }
+        }
+        flush_omitted(&mut out, &mut omitted);
+    }
+
+    (out, true)
+}
+
+fn flush_omitted(out: &mut String, omitted: &mut usize) {
+    if *omitted > 0 {
+        out.push_str(&format!("... ({} lines omitted)\n", omitted));
+        *omitted = 0;
+    }
+}
+
+// 按扩展名 (没有扩展名时按文件名) 判断语言, 不认识的文件不计入
+const LANGUAGES: &[(&str, &str)] = &[
+    ("rs", "Rust"),
+    ("toml", "TOML"),
+    ("py", "Python"),
+    ("js", "JavaScript"),
+    ("jsx", "JavaScript"),
+    ("mjs", "JavaScript"),
+    ("ts", "TypeScript"),
+    ("tsx", "TypeScript"),
+    ("go", "Go"),
+    ("java", "Java"),
+    ("kt", "Kotlin"),
+    ("c", "C"),
+    ("h", "C"),
+    ("cc", "C++"),
+    ("cpp", "C++"),
+    ("cxx", "C++"),
+    ("hpp", "C++"),
+    ("cs", "C#"),
+    ("rb", "Ruby"),
+    ("php", "PHP"),
+    ("swift", "Swift"),
+    ("scala", "Scala"),
+    ("dart", "Dart"),
+    ("lua", "Lua"),
+    ("ex", "Elixir"),
+    ("exs", "Elixir"),
+    ("sh", "Shell"),
+    ("bash", "Shell"),
+    ("zsh", "Shell"),
+    ("sql", "SQL"),
+    ("html", "HTML"),
+    ("css", "CSS"),
+    ("scss", "SCSS"),
+    ("vue", "Vue"),
+    ("md", "Markdown"),
+    ("json", "JSON"),
+    ("yaml", "YAML"),
+    ("yml", "YAML"),
+    ("Dockerfile", "Dockerfile"),
+    ("Makefile", "Makefile"),
+];
+
+fn language(path: &str) -> Option<&'static str> {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let key = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => ext,
+        _ => name,
+    };
+    LANGUAGES.iter().find(|(k, _)| *k == key).map(|(_, language)| *language)
+}
+
+// 每种语言改动 (增加加删除) 的行数, 按行数从多到少, 例如 `Languages: Rust (120 lines), TOML (8 lines)`
+pub fn language_summary(diff: &str) -> Option<String> {
+    let mut tally: Vec<(&str, usize)> = Vec::new();
+    for section in split_files(diff) {
+        let Some(language) = file_path(section).and_then(language) else {
+            continue;
+        };
+        // 第一个 @@ 之前是 +++/--- 等文件头
+        let lines = section
+            .lines()
+            .skip_while(|line| !line.starts_with("@@"))
+            .filter(|line| line.starts_with('+') || line.starts_with('-'))
+            .count();
+        match tally.iter_mut().find(|(l, _)| *l == language) {
+            Some((_, count)) => *count += lines,
+            None => tally.push((language, lines)),
+        }
+    }
+    tally.retain(|(_, lines)| *lines > 0);
+    if tally.is_empty() {
+        return None;
+    }
+    // 行数相同时保持出现的顺序
+    tally.sort_by_key(|(_, lines)| std::cmp::Reverse(*lines));
+    let languages: Vec<String> = tally
+        .iter()
+        .map(|(language, lines)| format!("{} ({} {})", language, lines, if *lines == 1 { "line" } else { "lines" }))
+        .collect();
+    Some(format!("Languages: {}", languages.join(", ")))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileStatus {
+    Added,
+    Deleted,
+    Modified,
+    // 原来的路径
+    Renamed(String),
+}
+
+// 一个文件增加和删除的行数, 二进制文件都是 0
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStat {
+    pub path: String,
+    pub status: FileStatus,
+    pub added: usize,
+    pub removed: usize,
+}
+
+// 和 `git diff --numstat` 一样按文件统计, 但直接从 diff 文本里算, --diff-file 时也能用
+pub fn file_stats(diff: &str) -> Vec<FileStat> {
+    let mut stats = Vec::new();
+    for section in split_files(diff) {
+        let Some(path) = file_path(section) else {
+            continue;
+        };
+        let mut status = FileStatus::Modified;
+        for line in section.lines().take_while(|line| !line.starts_with("@@")) {
+            if line.starts_with("new file mode") {
+                status = FileStatus::Added;
+            } else if line.starts_with("deleted file mode") {
+                status = FileStatus::Deleted;
+            } else if let Some(from) = line.strip_prefix("rename from ") {
+                status = FileStatus::Renamed(from.to_string());
+            }
+        }
+        let lines = section.lines().skip_while(|line| !line.starts_with("@@"));
+        let (mut added, mut removed) = (0, 0);
+        for line in lines {
+            if line.starts_with('+') {
+                added += 1;
+            } else if line.starts_with('-') {
+                removed += 1;
+            }
+        }
+        stats.push(FileStat {
+            path: path.to_string(),
+            status,
+            added,
+            removed,
+        });
+    }
+    stats
+}
+
+// 子模块指向的提交从 old 变成了 new
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubmoduleBump {
+    pub path: String,
+    pub old: String,
+    pub new: String,
+}
+
+// diff 里子模块只有 `-Subproject commit <old>` / `+Subproject commit <new>` 两行,
+// 新增或删除的子模块只有一边, 不算
+pub fn submodule_bumps(diff: &str) -> Vec<SubmoduleBump> {
+    let mut bumps = Vec::new();
+    for section in split_files(diff) {
+        let commit = |prefix: &str| {
+            section
+                .lines()
+                .find_map(|line| line.strip_prefix(prefix))
+                .map(|sha| sha.trim_end_matches("-dirty").trim().to_string())
+        };
+        if let (Some(path), Some(old), Some(new)) =
+            (file_path(section), commit("-Subproject commit "), commit("+Subproject commit "))
+        {
+            bumps.push(SubmoduleBump {
+                path: path.to_string(),
+                old,
+                new,
+            });
+        }
+    }
+    bumps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_sections_become_a_one_line_note() {
+        let text = "diff --git a/src/lib.rs b/src/lib.rs\n--- a/src/lib.rs\n+++ b/src/lib.rs\n@@ -1 +1 @@\n-a\n+b\n";
+        let binary = "diff --git a/logo.png b/logo.png\nindex 1234567..89abcde 100644\nBinary files a/logo.png and b/logo.png differ\n";
+        let garbled = format!("diff --git a/data.bin b/data.bin\n{}\n", "\u{fffd}".repeat(200));
+        let diff = format!("{}{}{}", text, binary, garbled);
+
+        assert_eq!(
+            summarize_binary(&diff),
+            format!("{}Binary file changed: logo.png\nBinary file changed: data.bin\n", text)
+        );
+    }
+
+    #[test]
+    fn file_path_is_the_new_path_unless_the_file_was_deleted() {
+        let renamed = "diff --git a/old.rs b/new.rs\nsimilarity index 90%\nrename from old.rs\nrename to new.rs\n\
+                       --- a/old.rs\n+++ b/new.rs\n@@ -1 +1 @@\n-a\n+b\n";
+        assert_eq!(file_path(renamed), Some("new.rs"));
+        let deleted = "diff --git a/gone.rs b/gone.rs\ndeleted file mode 100644\n\
+                       --- a/gone.rs\n+++ /dev/null\n@@ -1 +0,0 @@\n-a\n";
+        assert_eq!(file_path(deleted), Some("gone.rs"));
+        let added = "diff --git a/new.rs b/new.rs\nnew file mode 100644\n--- /dev/null\n+++ b/new.rs\n@@ -0,0 +1 @@\n+a\n";
+        assert_eq!(file_path(added), Some("new.rs"));
+        let pure_rename = "diff --git a/old.rs b/new.rs\nsimilarity index 100%\nrename from old.rs\nrename to new.rs\n";
+        assert_eq!(file_path(pure_rename), Some("new.rs"));
+    }
+
+    #[test]
+    fn file_renamed_into_an_excluded_directory_is_left_out() {
+        let ignore = load_ignore(&std::env::temp_dir().join("gmh-no-repo"), &["vendor/".to_string()], false).unwrap();
+        let renamed = "diff --git a/lib.rs b/vendor/lib.rs\nsimilarity index 90%\nrename from lib.rs\nrename to vendor/lib.rs\n\
+                       --- a/lib.rs\n+++ b/vendor/lib.rs\n@@ -1 +1 @@\n-a\n+b\n";
+
+        let (diff, excluded) = exclude_files(renamed, &ignore);
+
+        assert_eq!(excluded, ["vendor/lib.rs"]);
+        assert_eq!(diff, "(changes to vendor/lib.rs omitted)\n");
+    }
+}
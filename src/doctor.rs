@@ -0,0 +1,136 @@
+use std::time::Instant;
+
+use clap::ValueEnum;
+
+use gmh::git::{is_git_repository, repo_root, run_git};
+use gmh::provider::{self, find_api_key, GenerateOptions, HttpOptions};
+use gmh::GmhError;
+
+use crate::config::{self, Settings};
+use crate::Cli;
+
+const PING_SYSTEM_PROMPT: &str = "Reply with the single word OK.";
+
+// key 只显示开头和结尾几个字符, 足够分辨用的是哪一个
+fn redact(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() <= 12 {
+        return "*".repeat(chars.len());
+    }
+    let head: String = chars[..3].iter().collect();
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}...{}", head, tail)
+}
+
+#[derive(Default)]
+struct Checks {
+    failed: usize,
+}
+
+impl Checks {
+    fn check(&mut self, ok: bool, name: &str, detail: &str) {
+        println!("{} {}: {}", if ok { "✓" } else { "✗" }, name, detail);
+        if !ok {
+            self.failed += 1;
+        }
+    }
+
+    fn result(&self) -> Result<(), GmhError> {
+        match self.failed {
+            0 => Ok(()),
+            1 => Err(GmhError::Config("1 check failed".to_string())),
+            n => Err(GmhError::Config(format!("{} checks failed", n))),
+        }
+    }
+}
+
+// 依次检查 git、仓库、配置、API key 和一次最小的请求, 每项打印 ✓/✗
+pub async fn run(cli: &Cli) -> Result<(), GmhError> {
+    let mut checks = Checks::default();
+
+    match run_git(["--version"]) {
+        Ok(output) => checks.check(true, "git", String::from_utf8_lossy(&output.stdout).trim()),
+        Err(err) => checks.check(false, "git", &err.to_string()),
+    }
+    match is_git_repository() {
+        Ok(true) => checks.check(true, "repository", &repo_root().map_or_else(String::new, |r| r.display().to_string())),
+        Ok(false) => checks.check(false, "repository", "the current directory is not inside a Git repository"),
+        Err(err) => checks.check(false, "repository", &err.to_string()),
+    }
+
+    let settings = config::load_env_files(&cli.env_file)
+        .and_then(|_| config::load_config(repo_root().as_deref()))
+        .and_then(|config| Settings::resolve(cli, config));
+    let settings = match settings {
+        Ok(settings) => settings,
+        Err(err) => {
+            checks.check(false, "config", &err.to_string());
+            return checks.result();
+        }
+    };
+    let provider_name = settings
+        .provider
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default();
+    checks.check(
+        true,
+        "config",
+        &format!(
+            "provider {}, model {}, api base {}, timeout {}s",
+            provider_name,
+            settings.model,
+            settings.api_base.as_deref().unwrap_or("(provider default)"),
+            settings.timeout
+        ),
+    );
+
+    let key = match settings.provider.api_key_vars() {
+        Some(vars) => match find_api_key(vars) {
+            Some((var, key)) => {
+                checks.check(true, "API key", &format!("{}={}", var, redact(&key)));
+                true
+            }
+            None => {
+                checks.check(false, "API key", &GmhError::MissingApiKey(vars[0]).to_string());
+                false
+            }
+        },
+        None => {
+            checks.check(true, "API key", "not needed");
+            true
+        }
+    };
+
+    if key {
+        let http = HttpOptions {
+            timeout: settings.timeout,
+            max_retries: 0,
+            max_wait: settings.max_wait,
+            proxy: cli.proxy.clone(),
+            client: None,
+            api_key: None,
+            headers: cli.headers.clone(),
+            dump_request: cli.dump_request.clone(),
+            dump_response: cli.dump_response.clone(),
+        };
+        let opts = GenerateOptions {
+            model: settings.model.clone(),
+            system_prompt: PING_SYSTEM_PROMPT.to_string(),
+            ..GenerateOptions::default()
+        };
+        let start = Instant::now();
+        let result = match provider::build(settings.provider, settings.api_base.as_deref(), http) {
+            Ok(provider) => provider.generate("ping", &opts).await,
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(_) => checks.check(true, "API request", &format!("answered in {:.1}s", start.elapsed().as_secs_f64())),
+            Err(err) => checks.check(false, "API request", &err.to_string()),
+        }
+    } else {
+        checks.check(false, "API request", "skipped, no API key");
+    }
+
+    checks.result()
+}
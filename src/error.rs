@@ -0,0 +1,160 @@
+use reqwest::StatusCode;
+use std::{fmt, io};
+
+#[derive(Debug)]
+pub enum GmhError {
+    Canceled(&'static str),
+    // 确认时 stdin 已经到了 EOF
+    NoInput,
+    // gmh lint 发现的问题数
+    LintFailed(usize),
+    NotAGitRepo,
+    // 通过 GIT 指定的路径
+    GitNotFound(Option<String>),
+    GitCommand(String),
+    // git 找到了但启动或等待它失败
+    GitSpawn(io::Error),
+    Http(reqwest::Error),
+    Timeout(u64),
+    Api { status: StatusCode, body: String },
+    NoChoices,
+    EmptyMessage,
+    // 达到 max_tokens 仍没写完
+    Truncated,
+    InvalidSplit(String),
+    // 找到疑似密钥的位置数
+    SecretsFound(usize),
+    MissingApiKey(&'static str),
+    Config(String),
+    Editor(String),
+    Postprocess(String),
+    Clipboard(String),
+    File { path: String, source: io::Error },
+    Io(io::Error),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for GmhError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GmhError::Canceled(msg) => write!(f, "{}", msg),
+            GmhError::LintFailed(1) => write!(f, "1 problem found in the commit message"),
+            GmhError::LintFailed(count) => write!(f, "{} problems found in the commit message", count),
+            GmhError::NoInput => write!(f, "no input received; aborting. Pass --yes to commit without confirmation."),
+            GmhError::NotAGitRepo => write!(f, "Current directory is not a Git repository."),
+            GmhError::GitNotFound(None) => write!(f, "git executable not found on PATH"),
+            GmhError::GitNotFound(Some(path)) => write!(f, "git executable not found: {} (set by GIT)", path),
+            GmhError::GitCommand(err) => write!(f, "git failed: {}", err.trim()),
+            GmhError::GitSpawn(err) => write!(f, "cannot run git: {}", err),
+            GmhError::Http(err) => write!(f, "HTTP request failed: {}", err),
+            GmhError::Timeout(secs) => write!(f, "request timed out after {}s", secs),
+            GmhError::Api { status, body } => write!(f, "API error {}: {}", status.as_u16(), body.trim()),
+            GmhError::NoChoices => write!(f, "No response from the model"),
+            GmhError::EmptyMessage => write!(f, "The model returned an empty commit message; nothing was committed"),
+            GmhError::Truncated => write!(
+                f,
+                "the message was cut off at the token limit; nothing was committed. Raise --max-tokens or confirm it interactively"
+            ),
+            GmhError::InvalidSplit(err) => write!(f, "could not read the proposed commits: {}", err),
+            GmhError::SecretsFound(count) => write!(
+                f,
+                "the diff appears to contain secrets ({} found); nothing was sent. Remove them or pass --allow-secrets",
+                count
+            ),
+            GmhError::MissingApiKey(var) => write!(
+                f,
+                "{} (or GMH_API_KEY) is not set. Export it in your shell or add `{}=...` to a .env file in the repository.",
+                var, var
+            ),
+            GmhError::Config(err) => write!(f, "{}", err),
+            GmhError::Editor(err) => write!(f, "{}", err),
+            GmhError::Postprocess(err) => write!(f, "--postprocess failed: {}", err.trim()),
+            GmhError::Clipboard(err) => write!(f, "--copy failed: {}", err.trim()),
+            GmhError::File { path, source } => write!(f, "{}: {}", path, source),
+            GmhError::Io(err) => write!(f, "{}", err),
+            GmhError::Json(err) => write!(f, "invalid JSON from the API: {}", err),
+        }
+    }
+}
+
+impl GmhError {
+    // 进程退出码, 和 --help 里的说明保持一致
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            GmhError::Canceled(_) | GmhError::LintFailed(_) => 1,
+            GmhError::MissingApiKey(_)
+            | GmhError::SecretsFound(_)
+            | GmhError::Config(_)
+            | GmhError::Editor(_)
+            | GmhError::Postprocess(_)
+            | GmhError::Clipboard(_)
+            | GmhError::File { .. }
+            | GmhError::Io(_) => 2,
+            GmhError::NotAGitRepo | GmhError::GitCommand(_) | GmhError::GitSpawn(_) => 3,
+            GmhError::Http(_)
+            | GmhError::Timeout(_)
+            | GmhError::Api { .. }
+            | GmhError::NoChoices
+            | GmhError::EmptyMessage
+            | GmhError::Truncated
+            | GmhError::InvalidSplit(_)
+            | GmhError::Json(_) => 4,
+            GmhError::NoInput => 5,
+            // 和 shell 找不到命令时一样
+            GmhError::GitNotFound(_) => 127,
+        }
+    }
+
+    // 连不上 API 或者没有 key, --offline-fallback 时改用离线生成的消息
+    pub fn is_offline(&self) -> bool {
+        match self {
+            GmhError::Http(err) => err.is_connect(),
+            GmhError::Timeout(_) | GmhError::MissingApiKey(_) => true,
+            _ => false,
+        }
+    }
+}
+
+impl std::error::Error for GmhError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GmhError::Http(err) => Some(err),
+            GmhError::File { source, .. } => Some(source),
+            GmhError::GitSpawn(err) => Some(err),
+            GmhError::Io(err) => Some(err),
+            GmhError::Json(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for GmhError {
+    fn from(err: reqwest::Error) -> Self {
+        GmhError::Http(err)
+    }
+}
+
+impl From<io::Error> for GmhError {
+    fn from(err: io::Error) -> Self {
+        GmhError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for GmhError {
+    fn from(err: serde_json::Error) -> Self {
+        GmhError::Json(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_git_failures_exit_with_3() {
+        let io = || io::Error::new(io::ErrorKind::PermissionDenied, "denied");
+        assert_eq!(GmhError::Io(io()).exit_code(), 2);
+        assert_eq!(GmhError::GitSpawn(io()).exit_code(), 3);
+        assert_eq!(GmhError::GitCommand("fatal".to_string()).exit_code(), 3);
+    }
+}
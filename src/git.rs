@@ -0,0 +1,387 @@
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use serde::Deserialize;
+
+use crate::error::GmhError;
+use crate::message::normalize_newlines;
+
+// 设置了 GIT 时用它作为 git 的路径
+fn git_program() -> Option<String> {
+    env::var("GIT").ok().filter(|git| !git.is_empty())
+}
+
+// 找不到 git 时给出明确的错误而不是原始的 io 错误
+fn spawn_error(err: io::Error, program: Option<String>) -> GmhError {
+    match err.kind() {
+        ErrorKind::NotFound => GmhError::GitNotFound(program),
+        _ => GmhError::GitSpawn(err),
+    }
+}
+
+// 所有 git 调用都经过这里
+pub fn run_git<I, S>(args: I) -> Result<Output, GmhError>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let program = git_program();
+    Command::new(program.as_deref().unwrap_or("git"))
+        .args(args)
+        .output()
+        .map_err(|err| spawn_error(err, program))
+}
+
+// 和 run_git 一样, input 通过 stdin 交给 git (例如 commit -F -)
+fn run_git_with_input<I, S>(args: I, input: &str) -> Result<Output, GmhError>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let program = git_program();
+    let mut child = Command::new(program.as_deref().unwrap_or("git"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| spawn_error(err, program))?;
+    // git 提前退出时写入会失败, 以退出码和 stderr 为准
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(input.as_bytes());
+    }
+    child.wait_with_output().map_err(GmhError::GitSpawn)
+}
+
+// 成功时返回 stdout, 失败时把 stderr 作为错误信息
+fn git_stdout<I, S>(args: I) -> Result<String, GmhError>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let output = run_git(args)?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    } else {
+        Err(GmhError::GitCommand(String::from_utf8_lossy(&output.stderr).to_string()))
+    }
+}
+
+// git diff --diff-algorithm 的取值, 不指定时用 git 的默认 (或 diff.algorithm)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffAlgorithm {
+    Myers,
+    Minimal,
+    Patience,
+    Histogram,
+}
+
+impl DiffAlgorithm {
+    fn name(self) -> &'static str {
+        match self {
+            DiffAlgorithm::Myers => "myers",
+            DiffAlgorithm::Minimal => "minimal",
+            DiffAlgorithm::Patience => "patience",
+            DiffAlgorithm::Histogram => "histogram",
+        }
+    }
+}
+
+// 影响发给模型的 diff 内容的选项
+#[derive(Clone, Debug, Default)]
+pub struct DiffOptions {
+    // 把复制也显示成 copy from/to
+    pub find_copies: bool,
+    pub algorithm: Option<DiffAlgorithm>,
+    // git diff -U<n>, None 时是 git 的默认 (3 或 diff.context)
+    pub context_lines: Option<u32>,
+}
+
+impl DiffOptions {
+    // 重命名总是显示成 rename from/to, 不展开整个文件内容
+    fn args(&self) -> Vec<String> {
+        let mut args = vec!["--find-renames".to_string()];
+        if self.find_copies {
+            args.push("--find-copies".to_string());
+        }
+        if let Some(algorithm) = self.algorithm {
+            args.push(format!("--diff-algorithm={}", algorithm.name()));
+        }
+        if let Some(lines) = self.context_lines {
+            args.push(format!("-U{}", lines));
+        }
+        args
+    }
+}
+
+// 每个 pathspec 作为单独的参数传给 git, 不经过 shell
+pub async fn get_git_diff(paths: &[String], options: &DiffOptions) -> Result<String, GmhError> {
+    let options = options.args();
+    let mut args = vec!["diff", "--cached"];
+    args.extend(options.iter().map(String::as_str));
+    if !paths.is_empty() {
+        args.push("--");
+        args.extend(paths.iter().map(String::as_str));
+    }
+    git_stdout(args).map(|diff| normalize_newlines(&diff))
+}
+
+// --amend 时描述的是上一个提交的改动
+pub async fn get_last_commit_diff(options: &DiffOptions) -> Result<String, GmhError> {
+    let options = options.args();
+    let mut args = vec!["show", "--format="];
+    args.extend(options.iter().map(String::as_str));
+    args.push("HEAD");
+    git_stdout(args).map(|diff| normalize_newlines(&diff))
+}
+
+// <since>..HEAD 之间的改动, 用于 summarize
+pub async fn get_range_diff(since: &str, paths: &[String], options: &DiffOptions) -> Result<String, GmhError> {
+    let range = format!("{}..HEAD", since);
+    let options = options.args();
+    let mut args = vec!["diff"];
+    args.extend(options.iter().map(String::as_str));
+    args.push(&range);
+    if !paths.is_empty() {
+        args.push("--");
+        args.extend(paths.iter().map(String::as_str));
+    }
+    git_stdout(args).map(|diff| normalize_newlines(&diff))
+}
+
+// git log --oneline <since>..HEAD, 从旧到新
+pub fn commit_log(since: &str) -> Result<String, GmhError> {
+    let range = format!("{}..HEAD", since);
+    git_stdout(["log", "--oneline", "--reverse", &range]).map(|log| normalize_newlines(&log))
+}
+
+// 子模块一次更新最多列出这么多个提交
+const MAX_SUBMODULE_COMMITS: usize = 50;
+
+// 子模块在 old..new 之间的提交 (git log --oneline), 子模块没有 checkout 或者缺少这些提交时返回 None
+pub fn submodule_log(dir: &Path, old: &str, new: &str) -> Option<String> {
+    // 没有初始化的子模块是个空目录, 在里面运行 git 会找到外层仓库
+    if !dir.join(".git").exists() {
+        return None;
+    }
+    let dir = dir.to_str()?;
+    let max_count = format!("--max-count={}", MAX_SUBMODULE_COMMITS);
+    let range = format!("{}..{}", old, new);
+    let log = git_stdout(["-C", dir, "log", "--oneline", &max_count, &range]).ok()?;
+    Some(normalize_newlines(&log)).filter(|log| !log.trim().is_empty())
+}
+
+// --amend 时作为参考的原消息
+pub fn last_commit_message() -> Result<String, GmhError> {
+    git_stdout(["log", "-1", "--format=%B"]).map(|message| normalize_newlines(message.trim()))
+}
+
+// 这些文件或目录存在时, 对应的操作还没有结束, 提交消息由 git 准备.
+// rebase-apply 也是 git am 使用的目录
+const IN_PROGRESS_MARKERS: &[(&str, GitOperation)] = &[
+    ("MERGE_HEAD", GitOperation::Merge),
+    ("rebase-merge", GitOperation::Rebase),
+    ("rebase-apply", GitOperation::Rebase),
+    ("CHERRY_PICK_HEAD", GitOperation::CherryPick),
+    ("REVERT_HEAD", GitOperation::Revert),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GitOperation {
+    Merge,
+    Rebase,
+    CherryPick,
+    Revert,
+}
+
+impl GitOperation {
+    pub fn name(self) -> &'static str {
+        match self {
+            GitOperation::Merge => "merge",
+            GitOperation::Rebase => "rebase",
+            GitOperation::CherryPick => "cherry-pick",
+            GitOperation::Revert => "revert",
+        }
+    }
+}
+
+// 用 --git-path 找文件, 兼容 worktree
+pub fn operation_in_progress() -> Result<Option<GitOperation>, GmhError> {
+    let mut args = vec!["rev-parse"];
+    for (marker, _) in IN_PROGRESS_MARKERS {
+        args.extend(["--git-path", marker]);
+    }
+    let paths = git_stdout(args)?;
+    Ok(paths
+        .lines()
+        .zip(IN_PROGRESS_MARKERS)
+        .find(|(path, _)| Path::new(path).exists())
+        .map(|(_, (_, operation))| *operation))
+}
+
+// git diff --cached --quiet: 0 没有改动, 1 有改动
+pub fn has_staged_changes() -> Result<bool, GmhError> {
+    let output = run_git(["diff", "--cached", "--quiet"])?;
+
+    match output.status.code() {
+        Some(0) => Ok(false),
+        Some(1) => Ok(true),
+        _ => Err(GmhError::GitCommand(String::from_utf8_lossy(&output.stderr).to_string())),
+    }
+}
+
+// amend 时是上一次提交的 stat
+pub fn get_diff_stat(paths: &[String], amend: bool) -> Result<String, GmhError> {
+    let mut args = Vec::new();
+    if amend {
+        args.extend(["show", "--stat", "--format=", "--find-renames", "HEAD"]);
+    } else {
+        args.extend(["diff", "--cached", "--stat", "--find-renames"]);
+        if !paths.is_empty() {
+            args.push("--");
+            args.extend(paths.iter().map(String::as_str));
+        }
+    }
+    git_stdout(args)
+}
+
+// git add -A 或 -u, paths 为空时是整个仓库
+pub fn stage_changes(flag: &str, paths: &[String]) -> Result<(), GmhError> {
+    let mut args = vec!["add", flag];
+    if !paths.is_empty() {
+        args.push("--");
+        args.extend(paths.iter().map(String::as_str));
+    }
+    git_stdout(args).map(|_| ())
+}
+
+// 暂存区为空时用来提示是否忘了 git add
+pub fn unstaged_file_count(paths: &[String]) -> Result<usize, GmhError> {
+    let mut args = vec!["diff", "--name-only"];
+    if !paths.is_empty() {
+        args.push("--");
+        args.extend(paths.iter().map(String::as_str));
+    }
+    Ok(git_stdout(args)?.lines().count())
+}
+
+// git status --porcelain 里 ?? 开头的文件, -z 时路径不会被加引号
+pub fn untracked_files(paths: &[String]) -> Result<Vec<String>, GmhError> {
+    let mut args = vec!["status", "--porcelain", "-z", "--untracked-files=all"];
+    if !paths.is_empty() {
+        args.push("--");
+        args.extend(paths.iter().map(String::as_str));
+    }
+    let output = git_stdout(args)?;
+
+    let mut files = Vec::new();
+    let mut entries = output.split('\0');
+    while let Some(entry) = entries.next() {
+        if let Some(path) = entry.strip_prefix("?? ") {
+            files.push(path.to_string());
+        } else if entry.starts_with('R') || entry.starts_with('C') {
+            // 重命名和复制后面还跟着原来的路径
+            entries.next();
+        }
+    }
+    Ok(files)
+}
+
+// 和 /dev/null 比较得到新文件形式的 diff, 有差异时 git 返回 1.
+// path 是 git status 给出的相对仓库根目录的路径, --no-index 却按当前目录解析, 所以在根目录运行
+pub fn untracked_diff(path: &str) -> Result<String, GmhError> {
+    let root = repo_root().ok_or(GmhError::NotAGitRepo)?;
+    let root = root.to_string_lossy();
+    let output = run_git(["-C", &root, "diff", "--no-index", "--", "/dev/null", path])?;
+
+    match output.status.code() {
+        // 读不到文件时也是 1, 这时 stderr 里有错误信息
+        Some(0) | Some(1) if output.stderr.is_empty() => {
+            Ok(normalize_newlines(&String::from_utf8_lossy(&output.stdout)))
+        }
+        _ => Err(GmhError::GitCommand(String::from_utf8_lossy(&output.stderr).to_string())),
+    }
+}
+
+pub fn current_branch() -> Result<String, GmhError> {
+    Ok(git_stdout(["rev-parse", "--abbrev-ref", "HEAD"])?.trim().to_string())
+}
+
+// .git 目录里的文件, 兼容 worktree; 只有当前用户能写, 和 git 自己放 COMMIT_EDITMSG 的位置一样
+pub fn git_path(name: &str) -> Result<PathBuf, GmhError> {
+    Ok(PathBuf::from(git_stdout(["rev-parse", "--git-path", name])?.trim()))
+}
+
+// 不在仓库里时 (例如 --diff-file) 返回 None
+pub fn repo_root() -> Option<PathBuf> {
+    git_stdout(["rev-parse", "--show-toplevel"])
+        .ok()
+        .map(|root| PathBuf::from(root.trim()))
+}
+
+pub fn git_config(key: &str) -> Result<String, GmhError> {
+    let output = run_git(["config", key])?;
+
+    let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if output.status.success() && !value.is_empty() {
+        Ok(value)
+    } else {
+        Err(GmhError::GitCommand(format!("`git config {}` is not set", key)))
+    }
+}
+
+// commit.template 指向的文件内容, 未设置、读不到或只有空白时返回 None.
+// 和 git 一样, 相对路径相对仓库根目录
+pub fn commit_template() -> Option<String> {
+    let path = PathBuf::from(git_stdout(["config", "--path", "commit.template"]).ok()?.trim());
+    if path.as_os_str().is_empty() {
+        return None;
+    }
+    let path = match repo_root() {
+        Some(root) if path.is_relative() => root.join(path),
+        _ => path,
+    };
+    fs::read_to_string(path).ok().filter(|template| !template.trim().is_empty())
+}
+
+// paths 为空时提交暂存区的全部内容, extra_args 原样传给 git commit (--amend, --author 等).
+// 消息通过 stdin 用 -F - 传, 换行和特殊字符原样保留, 也不需要临时文件.
+// 成功时返回 git 的输出, 由调用方决定是否显示
+pub async fn commit_changes(
+    commit_message: &str,
+    paths: &[String],
+    extra_args: &[String],
+) -> Result<Output, GmhError> {
+    let mut args = vec!["commit"];
+    args.extend(extra_args.iter().map(String::as_str));
+    args.extend(["-F", "-"]);
+    if !paths.is_empty() {
+        args.push("--");
+        args.extend(paths.iter().map(String::as_str));
+    }
+    let output = run_git_with_input(args, commit_message)?;
+
+    if output.status.success() {
+        Ok(output)
+    } else {
+        // 钩子拒绝或没有可提交内容时, 原因可能在 stdout 里
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = if stderr.trim().is_empty() {
+            String::from_utf8_lossy(&output.stdout)
+        } else {
+            stderr
+        };
+        Err(GmhError::GitCommand(reason.to_string()))
+    }
+}
+
+// 交给 git 判断, 子目录和 .git 是文件的 worktree 也能识别
+pub fn is_git_repository() -> Result<bool, GmhError> {
+    let output = run_git(["rev-parse", "--is-inside-work-tree"])?;
+    Ok(output.status.success() && output.stdout.starts_with(b"true"))
+}
@@ -0,0 +1,62 @@
+use regex::Regex;
+
+pub const STRICT_HINT: &str = "Keep the gitmoji shortcode in front of the type, e.g. `:sparkles: feat: add login`.";
+pub const SYSTEM_PROMPT_HINT: &str = "Start the subject with the gitmoji shortcode that best fits the change, followed by a space and placed before any Conventional Commits type, e.g. `:sparkles:` for a new feature, `:bug:` for a bug fix, `:memo:` for documentation, `:recycle:` for refactoring, `:zap:` for performance, `:white_check_mark:` for tests, `:wrench:` for configuration.";
+
+// 常用的 gitmoji, 不在表里的 shortcode 原样保留
+const GITMOJIS: &[(&str, &str)] = &[
+    ("sparkles", "✨"),
+    ("bug", "🐛"),
+    ("ambulance", "🚑️"),
+    ("memo", "📝"),
+    ("art", "🎨"),
+    ("zap", "⚡️"),
+    ("fire", "🔥"),
+    ("recycle", "♻️"),
+    ("white_check_mark", "✅"),
+    ("test_tube", "🧪"),
+    ("lock", "🔒️"),
+    ("rocket", "🚀"),
+    ("lipstick", "💄"),
+    ("tada", "🎉"),
+    ("wrench", "🔧"),
+    ("construction_worker", "👷"),
+    ("green_heart", "💚"),
+    ("arrow_up", "⬆️"),
+    ("arrow_down", "⬇️"),
+    ("heavy_plus_sign", "➕"),
+    ("heavy_minus_sign", "➖"),
+    ("pencil2", "✏️"),
+    ("rewind", "⏪️"),
+    ("truck", "🚚"),
+    ("boom", "💥"),
+    ("bookmark", "🔖"),
+    ("rotating_light", "🚨"),
+    ("construction", "🚧"),
+    ("globe_with_meridians", "🌐"),
+    ("adhesive_bandage", "🩹"),
+    ("bulb", "💡"),
+    ("card_file_box", "🗃️"),
+    ("wastebasket", "🗑️"),
+    ("label", "🏷️"),
+    ("package", "📦️"),
+];
+
+pub fn emoji(shortcode: &str) -> Option<&'static str> {
+    GITMOJIS
+        .iter()
+        .find(|(code, _)| *code == shortcode)
+        .map(|(_, emoji)| *emoji)
+}
+
+// 只替换 subject 开头的 shortcode
+pub fn to_emoji(message: &str) -> String {
+    let re = Regex::new(r"^\s*:([a-z0-9_+-]+):").expect("invalid gitmoji regex");
+    let Some(caps) = re.captures(message) else {
+        return message.to_string();
+    };
+    match emoji(&caps[1]) {
+        Some(emoji) => format!("{}{}", emoji, &message[caps[0].len()..]),
+        None => message.to_string(),
+    }
+}
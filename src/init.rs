@@ -0,0 +1,133 @@
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+use clap::ValueEnum;
+
+use gmh::provider::ProviderKind;
+use gmh::GmhError;
+
+use crate::config;
+
+// 交互式地写出 config.toml, API key 单独写到旁边的 .env (0600)
+pub fn run() -> Result<(), GmhError> {
+    let config_path = config::config_path().ok_or_else(|| {
+        GmhError::Config("cannot find the home directory; set HOME or XDG_CONFIG_HOME".to_string())
+    })?;
+    if config_path.exists()
+        && !ask_yes_no(&format!(
+            "{} already exists. Overwrite it?",
+            config_path.display()
+        ))?
+    {
+        return Err(GmhError::Canceled("Setup canceled."));
+    }
+
+    let provider = loop {
+        let input = ask("Provider (deepseek/openai/anthropic/ollama/azure) [deepseek]: ")?;
+        if input.is_empty() {
+            break ProviderKind::Deepseek;
+        }
+        match ProviderKind::from_str(&input, true) {
+            Ok(provider) => break provider,
+            Err(_) => println!("Unknown provider '{}'.", input),
+        }
+    };
+
+    let api_key = match provider.api_key_vars() {
+        Some(&[var, ..]) => Some((
+            var,
+            ask(&format!(
+                "{} (leave empty to keep using the environment): ",
+                var
+            ))?,
+        )),
+        _ => None,
+    };
+
+    let default_model = provider.default_model();
+    let model = ask(&format!("Model [{}]: ", default_model))?;
+    let model = if model.is_empty() {
+        default_model.to_string()
+    } else {
+        model
+    };
+
+    if let Some(dir) = config_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let provider_name = provider
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default();
+    let text = format!(
+        "provider = {}\nmodel = {}\n",
+        toml::Value::String(provider_name),
+        toml::Value::String(model)
+    );
+    fs::write(&config_path, text).map_err(|source| file_error(&config_path, source))?;
+    println!("Wrote {}", config_path.display());
+
+    if let Some((var, key)) = api_key.filter(|(_, key)| !key.is_empty()) {
+        let env_path = config::env_path().expect("config path exists");
+        write_env(&env_path, var, &key)?;
+        println!("Wrote {} to {}", var, env_path.display());
+    }
+    Ok(())
+}
+
+fn ask(prompt: &str) -> Result<String, GmhError> {
+    print!("{}", prompt);
+    std::io::stdout().flush()?;
+    let mut input = String::new();
+    if std::io::stdin().read_line(&mut input)? == 0 {
+        return Err(GmhError::Canceled("Setup canceled."));
+    }
+    Ok(input.trim().to_string())
+}
+
+fn ask_yes_no(question: &str) -> Result<bool, GmhError> {
+    Ok(ask(&format!("{} (y/n) ", question))?.eq_ignore_ascii_case("y"))
+}
+
+// 保留 .env 里其他的变量, 只替换这一个
+fn write_env(path: &Path, var: &str, value: &str) -> Result<(), GmhError> {
+    let existing = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+        Err(source) => return Err(file_error(path, source)),
+    };
+    let prefix = format!("{}=", var);
+    let mut lines: Vec<&str> = existing
+        .lines()
+        .filter(|line| !line.trim_start().starts_with(&prefix))
+        .collect();
+    let entry = format!("{}={}", var, value);
+    lines.push(&entry);
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .map_err(|source| file_error(path, source))?;
+    // mode 只在新建文件时生效, 已有的文件也收紧权限
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    writeln!(file, "{}", lines.join("\n")).map_err(|source| file_error(path, source))?;
+    Ok(())
+}
+
+fn file_error(path: &Path, source: std::io::Error) -> GmhError {
+    GmhError::File {
+        path: path.display().to_string(),
+        source,
+    }
+}
@@ -0,0 +1,35 @@
+//! gmh (git commit message helper) 的核心逻辑, 命令行在 main.rs 里.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), gmh::GmhError> {
+//! let diff = gmh::get_staged_diff().await?;
+//! let generation = gmh::generate_commit_message(&diff, &gmh::GenerateOptions::default()).await?;
+//! println!("{}", generation.message);
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod diff;
+pub mod error;
+pub mod git;
+pub mod gitmoji;
+pub mod lint;
+pub mod message;
+pub mod pipeline;
+pub mod provider;
+pub mod secrets;
+pub mod split;
+
+pub use error::GmhError;
+pub use provider::{GenerateOptions, Generation, HttpOptions, Provider, ProviderKind, Usage};
+
+// 暂存区的全部改动, 等同于 `git diff --cached --find-renames`
+pub async fn get_staged_diff() -> Result<String, GmhError> {
+    git::get_git_diff(&[], &git::DiffOptions::default()).await
+}
+
+// 使用默认的 DeepSeek provider (DEEPSEEK_API_KEY, 兼容 OPENAI_API_KEY); 其他 provider 用 provider::build 创建后调用 generate
+pub async fn generate_commit_message(diff: &str, opts: &GenerateOptions) -> Result<Generation, GmhError> {
+    let provider = provider::build(ProviderKind::Deepseek, None, HttpOptions::default())?;
+    provider.generate(diff, opts).await
+}
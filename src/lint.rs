@@ -0,0 +1,77 @@
+use crate::message::{conventional_regex, CONVENTIONAL_TYPES};
+
+// gmh lint 检查的规则, 和生成消息时用的设置一致
+pub struct Rules {
+    pub max_subject_length: usize,
+    pub conventional: bool,
+    // 设置时 scope 必须是这个
+    pub scope: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Problem {
+    // 文件里的行号, 从 1 开始
+    pub line: usize,
+    pub rule: &'static str,
+    pub message: String,
+}
+
+impl Problem {
+    fn new(line: usize, rule: &'static str, message: String) -> Self {
+        Problem { line, rule, message }
+    }
+}
+
+// 和 git 一样忽略注释行和 `git commit -v` 剪刀线之后的 diff, 行号仍然是原文件里的
+pub fn check(text: &str, comment_char: &str, rules: &Rules) -> Vec<Problem> {
+    let scissors = format!("{} ------------------------ >8 ------------------------", comment_char);
+    let lines: Vec<(usize, &str)> = text
+        .lines()
+        .take_while(|line| *line != scissors)
+        .enumerate()
+        .map(|(i, line)| (i + 1, line))
+        .filter(|(_, line)| !line.starts_with(comment_char))
+        .skip_while(|(_, line)| line.trim().is_empty())
+        .collect();
+    let Some(&(subject_line, subject)) = lines.first() else {
+        return vec![Problem::new(1, "empty", "the message is empty".to_string())];
+    };
+
+    let mut problems = Vec::new();
+    let len = subject.trim_end().chars().count();
+    if len > rules.max_subject_length {
+        problems.push(Problem::new(
+            subject_line,
+            "subject-length",
+            format!("subject is {} characters, longer than {}", len, rules.max_subject_length),
+        ));
+    }
+    if rules.conventional {
+        match conventional_regex().captures(subject) {
+            Some(captures) => {
+                let scope = captures.get(2).map(|m| m.as_str().trim_matches(|c| c == '(' || c == ')'));
+                if let Some(expected) = rules.scope.as_deref().filter(|expected| scope != Some(*expected)) {
+                    problems.push(Problem::new(subject_line, "scope", format!("the scope must be `{}`", expected)));
+                }
+            }
+            None => problems.push(Problem::new(
+                subject_line,
+                "conventional",
+                format!(
+                    "subject must look like `type(scope): subject` with a type in {}",
+                    CONVENTIONAL_TYPES.join(", ")
+                ),
+            )),
+        }
+    }
+    if let Some(&(line, _)) = lines.get(1).filter(|(_, text)| !text.trim().is_empty()) {
+        problems.push(Problem::new(line, "blank-line", "the subject must be followed by a blank line".to_string()));
+    }
+    for &(line, text) in &lines {
+        if text != text.trim_end() {
+            problems.push(Problem::new(line, "trailing-whitespace", "line ends with whitespace".to_string()));
+        }
+    }
+    problems.sort_by_key(|problem| problem.line);
+    problems
+}
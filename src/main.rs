@@ -1,178 +1,1847 @@
-use std::process::Command;
-use reqwest::Client;
-use serde::{Deserialize, Serialize};
-use dotenv::dotenv;
+mod cache;
+mod clipboard;
+mod config;
+mod doctor;
+mod init;
+mod tui;
+
+use std::process::{Command, Output, Stdio};
+use async_trait::async_trait;
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+use futures_util::stream::{self, StreamExt};
 use std::{env, process};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::io::{IsTerminal, Read, Write};
+use std::time::{Duration, Instant};
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use config::Settings;
+use gmh::git::{
+    commit_changes, commit_log, commit_template, current_branch, get_diff_stat, get_git_diff, get_last_commit_diff,
+    get_range_diff, git_config, git_path, has_staged_changes, is_git_repository, last_commit_message,
+    operation_in_progress, repo_root, run_git, stage_changes, submodule_log, unstaged_file_count, untracked_diff,
+    untracked_files, DiffAlgorithm, DiffOptions, GitOperation,
+};
+use gmh::message::{
+    append_signoff, apply_template, comment_char, issue_from_branch, language_name, normalize_newlines,
+    offline_message, render_prompt_template, set_scope, split_message, strip_comments, subject_len,
+    template_instruction, truncate_body_words, validate_scope, wrap_message, MessageFormat, AMEND_HINT, BODY_HINT,
+    EXPLAIN_SYSTEM_PROMPT, SUBJECT_MAX_LEN, SUMMARIZE_SYSTEM_PROMPT,
+};
+use gmh::pipeline::{self, add_usage};
+use gmh::provider::{
+    self, GenerateOptions, Generation, HttpOptions, Message, OnChunk, Provider, ProviderKind, Usage,
+};
+use gmh::{diff, gitmoji, lint, secrets, split, GmhError};
+
+const EXIT_CODES_HELP: &str = "Exit codes:
+  0    success (or nothing to commit)
+  1    canceled by the user, or gmh lint found problems
+  2    configuration or local I/O error, e.g. missing API key
+  3    git error, or git could not be run
+  4    API or network error
+  5    no answer to a confirmation prompt (stdin reached EOF)
+  127  git executable not found
+  130  interrupted with Ctrl-C";
+
+// 未指定的参数依次从环境变量、~/.config/gmh/config.toml 读取
+#[derive(Parser, Debug)]
+#[command(name = "gmh", version, about = "git commit message helper", after_help = EXIT_CODES_HELP)]
+pub struct Cli {
+    /// LLM provider (env: GMH_PROVIDER, default: deepseek)
+    #[arg(long, value_enum)]
+    provider: Option<ProviderKind>,
+
+    /// Model name sent to the API (env: GMH_MODEL, default depends on the provider).
+    /// For --provider azure this is the deployment name
+    #[arg(long, value_parser = clap::builder::NonEmptyStringValueParser::new())]
+    model: Option<String>,
+
+    /// Retry once with this model when the message from --model is too short (--min-message-length)
+    /// or not a Conventional Commit under --conventional, e.g. a reasoning model
+    #[arg(long, value_name = "MODEL", value_parser = clap::builder::NonEmptyStringValueParser::new())]
+    model_fallback: Option<String>,
 
-#[derive(Serialize, Debug)]
-struct DeepSeekRequest {
-    model: String,
-    messages: Vec<Message>,
+    /// Base URL of the provider API (env: GMH_API_BASE, default depends on the provider).
+    /// For --provider azure the api-version is read from ?api-version= here, then GMH_AZURE_API_VERSION
+    #[arg(long, value_parser = clap::builder::NonEmptyStringValueParser::new())]
+    api_base: Option<String>,
+
+    /// Stream the message to stdout while it is being generated (env: GMH_STREAM); only when stdout is a terminal
+    #[arg(long, overrides_with = "no_stream")]
     stream: bool,
+
+    /// Do not stream, even if GMH_STREAM or the config file turns streaming on
+    #[arg(long, overrides_with = "stream")]
+    no_stream: bool,
+
+    /// Retries on timeouts, 429 and 5xx responses [default: 3]
+    #[arg(long)]
+    max_retries: Option<u32>,
+
+    /// On 429, wait as long as Retry-After (or x-ratelimit-reset) asks, but at most this many seconds [default: 60]
+    #[arg(long, value_name = "SECONDS")]
+    max_wait: Option<u64>,
+
+    /// Timeout in seconds for the whole request, including reading the body [default: 30]
+    #[arg(long)]
+    timeout: Option<u64>,
+
+    /// Send API requests through this proxy instead of HTTPS_PROXY / HTTP_PROXY (NO_PROXY still applies)
+    #[arg(long, value_name = "URL")]
+    proxy: Option<String>,
+
+    /// Write the API request (URL, headers with keys redacted, JSON body) to this file, for bug reports
+    #[arg(long, value_name = "PATH", help_heading = "Debugging")]
+    dump_request: Option<PathBuf>,
+
+    /// Write the API response (status, headers, body) to this file
+    #[arg(long, value_name = "PATH", help_heading = "Debugging")]
+    dump_response: Option<PathBuf>,
+
+    /// Extra HTTP header sent with every API request, e.g. "Helicone-Auth: Bearer sk-..." (repeatable)
+    #[arg(long = "header", visible_alias = "provider-header", value_name = "KEY: VALUE", value_parser = parse_header)]
+    headers: Vec<(String, String)>,
+
+    /// Generate Conventional Commits style messages (type(scope): subject)
+    #[arg(long)]
+    conventional: bool,
+
+    /// Scope to put in `type(scope):`, e.g. api or parser; implies --conventional
+    #[arg(long, value_parser = validate_scope)]
+    scope: Option<String>,
+
+    /// Print the generated message and exit without committing
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Copy the generated message to the clipboard instead of committing (implies --dry-run)
+    #[arg(long, conflicts_with_all = ["candidates", "split", "sweep", "hook", "tui"])]
+    copy: bool,
+
+    /// Read the diff from a file ('-' for stdin) instead of `git diff --cached`; implies --dry-run
+    #[arg(long, value_name = "PATH")]
+    diff_file: Option<String>,
+
+    /// Print the diff stat to stderr before the message (always shown with --verbose)
+    #[arg(long)]
+    show_diff: bool,
+
+    /// Tell the model which languages changed, e.g. "Languages: Rust (120 lines), TOML (8 lines)"
+    #[arg(long)]
+    lang_hint: bool,
+
+    /// Run `git add -A` (limited to --path if given) before generating, after confirmation unless --yes
+    #[arg(short = 'a', long, conflicts_with_all = ["diff_file", "amend", "hook", "stage_tracked"])]
+    stage_all: bool,
+
+    /// Like --stage-all but with `git add -u`, staging modified and deleted tracked files only
+    #[arg(long, conflicts_with_all = ["diff_file", "amend", "hook"])]
+    stage_tracked: bool,
+
+    /// Also detect copied files (git diff --find-copies), renames are always detected
+    #[arg(long)]
+    find_copies: bool,
+
+    /// Diff algorithm for the diff sent to the model; patience or histogram often group refactors
+    /// into cleaner hunks [default: git's diff.algorithm]
+    #[arg(long, value_enum, value_name = "NAME")]
+    diff_algorithm: Option<DiffAlgorithm>,
+
+    /// Lines of context around each change (git diff -U<n>); more help the model, fewer save tokens [default: 3]
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(0..=config::MAX_CONTEXT_LINES as i64))]
+    context_lines: Option<u32>,
+
+    /// Also show the model untracked files (git status --porcelain), they are not staged or committed
+    #[arg(long, conflicts_with_all = ["diff_file", "amend"])]
+    include_untracked: bool,
+
+    /// Do not exclude common lockfiles (Cargo.lock, package-lock.json, ...) from the diff; .gmhignore still applies
+    #[arg(long)]
+    no_default_ignore: bool,
+
+    /// Truncate the diff sent to the model above this many tokens (estimated) [default: 16000]
+    #[arg(long)]
+    max_diff_tokens: Option<usize>,
+
+    /// Do not call the API; build a basic message from the diff stat, e.g. "Update 3 files (+20/-4) in src"
+    #[arg(long, conflicts_with_all = ["candidates", "split", "sweep"])]
+    offline: bool,
+
+    /// Fall back to the --offline message when the API cannot be reached or no API key is set
+    #[arg(long)]
+    offline_fallback: bool,
+
+    /// Print an estimate of the prompt tokens the request would use and exit without calling the API.
+    /// Counts 4 characters per token, not with the model's tokenizer
+    #[arg(long, visible_alias = "count-only", conflicts_with_all = ["split", "sweep", "offline"])]
+    count_tokens: bool,
+
+    /// Send the diff even if it looks like it contains API keys, private keys or tokens
+    #[arg(long)]
+    allow_secrets: bool,
+
+    /// Commit without asking for confirmation
+    #[arg(short, long)]
+    yes: bool,
+
+    /// At the confirmation prompt, pressing Enter without an answer commits
+    #[arg(long, conflicts_with = "yes")]
+    default_yes: bool,
+
+    /// Text of the confirmation prompt shown before committing
+    #[arg(long, value_name = "TEXT", value_parser = clap::builder::NonEmptyStringValueParser::new())]
+    confirm_prompt: Option<String>,
+
+    /// Review the message full-screen with the diff stat: (r)egenerate, (e)dit, (a)ccept or (q)uit.
+    /// Falls back to the normal prompt when not run in a terminal
+    #[arg(
+        long,
+        visible_alias = "interactive",
+        conflicts_with_all = ["yes", "dry_run", "candidates", "split", "sweep", "hook"]
+    )]
+    tui: bool,
+
+    /// Pass --no-verify to git commit, skipping the pre-commit and commit-msg hooks
+    #[arg(long)]
+    no_verify: bool,
+
+    /// Override the commit author as "Name <email>", passed to git commit --author
+    #[arg(long, value_parser = parse_author)]
+    author: Option<String>,
+
+    /// Override the author date, passed to git commit --date
+    #[arg(long, value_parser = clap::builder::NonEmptyStringValueParser::new())]
+    date: Option<String>,
+
+    /// GPG-sign the commit, passed to git commit as -S or --gpg-sign=KEYID
+    #[arg(
+        short = 'S',
+        long,
+        value_name = "KEYID",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ""
+    )]
+    gpg_sign: Option<String>,
+
+    /// Append a Signed-off-by trailer using git's user.name and user.email
+    #[arg(short, long)]
+    signoff: bool,
+
+    /// Only describe staged changes matching this pathspec (repeatable)
+    #[arg(long = "path", value_name = "PATHSPEC")]
+    paths: Vec<String>,
+
+    /// Commit only the --path pathspecs (like `git commit -- <paths>`) instead of everything staged
+    #[arg(long, requires = "paths")]
+    only: bool,
+
+    /// Text prepended to the message, `{branch}` is replaced by the current branch (env: GMH_PREFIX)
+    #[arg(long)]
+    prefix: Option<String>,
+
+    /// Text appended to the message, `{branch}` is replaced by the current branch (env: GMH_SUFFIX)
+    #[arg(long)]
+    suffix: Option<String>,
+
+    /// Message style: a single line, a summary with a bulleted body, or Added/Changed/Fixed groups
+    #[arg(long, value_enum, value_name = "PRESET")]
+    format: Option<MessageFormat>,
+
+    /// Output format; `json` prints a single object to stdout and implies --dry-run unless --yes is given
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+
+    /// Print token usage (and cost if GMH_PROMPT_PRICE/GMH_COMPLETION_PRICE are set, per 1M tokens) to stderr;
+    /// repeat (-vv, -vvv) for debug logs, RUST_LOG overrides the level
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Sampling temperature between 0.0 and 2.0 [default: 0.2]
+    #[arg(long, value_parser = config::parse_temperature)]
+    temperature: Option<f32>,
+
+    /// Send this seed and use temperature 0 for reproducible messages, e.g. in CI. Only as deterministic
+    /// as the provider allows: OpenAI-compatible APIs and Ollama accept a seed, Anthropic ignores it
+    #[arg(long, value_name = "N", conflicts_with_all = ["temperature", "sweep"])]
+    seed: Option<u64>,
+
+    /// Upper bound on tokens generated for the message
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    max_tokens: Option<u32>,
+
+    /// Language of the generated message, e.g. ja, zh, es, de, fr (env: GMH_LANG) [default: en]
+    #[arg(long, value_name = "CODE", value_parser = clap::builder::NonEmptyStringValueParser::new())]
+    lang: Option<String>,
+
+    /// Extra hint sent after the diff, e.g. "hotfix for prod" (repeatable, counts toward token usage)
+    #[arg(long, value_name = "TEXT")]
+    context: Vec<String>,
+
+    /// Start the subject with a gitmoji shortcode such as :sparkles: (combines with --conventional)
+    #[arg(long)]
+    gitmoji: bool,
+
+    /// With --gitmoji, replace the leading shortcode with the emoji itself
+    #[arg(long, requires = "gitmoji")]
+    emoji: bool,
+
+    /// Keep only the subject line of the generated message
+    #[arg(long, conflicts_with_all = ["body_only", "candidates", "split", "sweep"])]
+    subject_only: bool,
+
+    /// Keep only the body of the generated message; committing needs --subject
+    #[arg(long, conflicts_with_all = ["candidates", "split", "sweep"])]
+    body_only: bool,
+
+    /// With --body-only, the subject line to commit the generated body under
+    #[arg(long, value_name = "TEXT", requires = "body_only", value_parser = clap::builder::NonEmptyStringValueParser::new())]
+    subject: Option<String>,
+
+    /// Pipe the final message through this shell command (message on stdin, result from stdout) before committing
+    #[arg(long, value_name = "CMD", value_parser = clap::builder::NonEmptyStringValueParser::new())]
+    postprocess: Option<String>,
+
+    /// Hard-wrap body lines at 72 columns
+    #[arg(long)]
+    wrap: bool,
+
+    /// Do not use git's commit.template as a skeleton for the message
+    #[arg(long)]
+    no_commit_template: bool,
+
+    /// Regenerate once, asking for more detail, when the message is empty or very short
+    #[arg(long)]
+    retry_empty: bool,
+
+    /// With --retry-empty, messages shorter than this many characters are regenerated [default: 10]
+    #[arg(long, value_name = "N")]
+    min_message_length: Option<usize>,
+
+    /// Ask for a message of about N words; the body is truncated if the reply is more than twice that
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    max_words: Option<u32>,
+
+    /// Regenerate once, then truncate, when the subject is longer than N characters
+    #[arg(long, value_name = "N")]
+    max_subject_length: Option<usize>,
+
+    /// Load environment variables from this file (repeatable, later files win),
+    /// on top of ~/.config/gmh/.env, .env and .env.local
+    #[arg(long, value_name = "PATH")]
+    env_file: Vec<PathBuf>,
+
+    /// Read the system prompt from this file (env: GMH_SYSTEM_PROMPT holds the prompt text)
+    #[arg(long, value_name = "PATH")]
+    prompt_file: Option<PathBuf>,
+
+    /// Build the whole prompt from this file instead of the built-in system prompt; {{diff}}, {{branch}},
+    /// {{stat}} and {{lang}} are replaced, other text is sent as is
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["split", "prompt_file"])]
+    template: Option<PathBuf>,
+
+    /// Do not read or write the cache of generated messages (~/.cache/gmh, kept for 1 hour)
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Generate this many candidate messages and pick one
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=10))]
+    candidates: u32,
+
+    /// How many candidate requests to send at the same time when the provider returns fewer than --candidates
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u64).range(1..=10))]
+    concurrency: u64,
+
+    /// Ask the model to group the staged files into separate commits and commit them one by one
+    #[arg(long, conflicts_with_all = ["candidates", "amend", "hook", "only"])]
+    split: bool,
+
+    /// Debugging aid: generate at temperatures 0.0, 0.5 and 1.0 and print each result without committing
+    #[arg(long, help_heading = "Debugging", conflicts_with_all = ["candidates", "split", "hook", "temperature"])]
+    sweep: bool,
+
+    /// Regenerate the message of the last commit and amend it
+    #[arg(long, conflicts_with_all = ["diff_file", "paths"])]
+    amend: bool,
+
+    /// With --amend, amend even if there are staged changes that would be swept in
+    #[arg(long, requires = "amend")]
+    force: bool,
+
+    /// Run as a prepare-commit-msg hook: write the message into the file git passes in
+    #[arg(long, requires = "hook_args")]
+    hook: bool,
+
+    /// Arguments git passes to the hook: <MSG_FILE> [SOURCE] [SHA]
+    #[arg(requires = "hook", value_name = "HOOK_ARGS", num_args = 1..=3)]
+    hook_args: Vec<String>,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Serialize)]
+struct JsonSplitOutput<'a> {
+    commits: &'a [split::SplitCommit],
+    model: &'a str,
+    provider: ProviderKind,
+    usage: Option<&'a Usage>,
+}
+
+#[derive(Serialize)]
+struct JsonSweepResult {
+    temperature: f32,
+    message: String,
+    usage: Option<Usage>,
+}
+
+// --output json 时写到 stdout 的唯一内容
+#[derive(Serialize)]
+struct JsonOutput<'a> {
+    message: &'a str,
+    model: &'a str,
+    provider: ProviderKind,
+    usage: Option<&'a Usage>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    candidates: &'a [String],
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Interactively write ~/.config/gmh/config.toml and store the API key in ~/.config/gmh/.env
+    Init,
+    /// Check git, the repository, the config and the API key, and send a tiny test request
+    Doctor,
+    /// Explain in plain English what the staged change does and why, without committing
+    Explain,
+    /// Summarize everything since a ref, e.g. for a pull request description, without committing
+    Summarize {
+        /// Summarize the commits in <REF>..HEAD
+        #[arg(long, value_name = "REF", value_parser = parse_ref)]
+        since: String,
+    },
+    /// Check a hand-written message (e.g. from a commit-msg hook) against the subject length,
+    /// --conventional/--scope and whitespace rules without calling the API
+    Lint {
+        /// The message file, usually .git/COMMIT_EDITMSG
+        file: PathBuf,
+    },
+    /// Install a prepare-commit-msg hook that runs `gmh --hook`
+    InstallHook {
+        /// Overwrite an existing hook that was not installed by gmh
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+const SWEEP_TEMPERATURES: [f32; 3] = [0.0, 0.5, 1.0];
+
+const SPINNER_FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+
+const HOOK_MARKER: &str = "# installed by gmh";
+const HOOK_SCRIPT: &str = "#!/bin/sh
+# installed by gmh
+# never block the commit if generation fails
+gmh --hook \"$@\" || echo \"gmh: failed to generate a commit message\" >&2
+exit 0
+";
+
+// 二进制内容按 lossy 转换, 不会因为非法 UTF-8 失败; CRLF 换成 \n
+fn read_diff_file(path: &str) -> Result<String, GmhError> {
+    let mut bytes = Vec::new();
+    if path == "-" {
+        std::io::stdin().read_to_end(&mut bytes)?;
+    } else {
+        bytes = fs::read(path).map_err(|source| GmhError::File {
+            path: path.to_string(),
+            source,
+        })?;
+    }
+    Ok(normalize_newlines(&String::from_utf8_lossy(&bytes)))
 }
 
-#[derive(Serialize, Debug)]
-struct Message {
-    role: String,
-    content: String,
+fn warn_long_subject(message: &str) {
+    let subject_len = subject_len(message);
+    if subject_len > SUBJECT_MAX_LEN {
+        eprintln!(
+            "Warning: subject is {} characters, longer than {}.",
+            subject_len, SUBJECT_MAX_LEN
+        );
+    }
 }
 
-#[derive(Deserialize, Debug)]
-struct Usage {
-    prompt_tokens: u32,
-    completion_tokens: u32,
-    total_tokens: u32,
-    prompt_cache_hit_tokens: u32,
-    prompt_cache_miss_tokens: u32,
+// 生成之后的处理: shortcode 换成 emoji, 按需折行
+fn finish_message(message: &str, settings: &Settings) -> String {
+    let mut message = if settings.emoji {
+        gitmoji::to_emoji(message)
+    } else {
+        message.to_string()
+    };
+    // 模型不一定照做, 直接改 subject
+    if let Some(scope) = &settings.scope {
+        message = set_scope(&message, scope);
+    }
+    if settings.wrap {
+        message = wrap_message(&message);
+    }
+    message
 }
 
+// 只在超出太多时才截断, 稍微多几个词不要紧
+fn limit_words(message: String, settings: &Settings) -> String {
+    match settings.max_words {
+        Some(max_words) if message.split_whitespace().count() > max_words * 2 => {
+            eprintln!("Warning: message is over {} words, truncating the body.", max_words * 2);
+            truncate_body_words(&message, max_words)
+        }
+        _ => message,
+    }
+}
 
-#[derive(Deserialize, Debug)]
-struct DeepSeekResponse {
-    id: String,
-    object: String,
-    created: u64,
-    model: String,
-    choices: Vec<Choice>,
-    usage: Usage,
-    system_fingerprint: String,
+// --subject-only 只留第一段, --body-only 只留空行之后的部分, 有 --subject 时用它作为标题
+fn select_part(message: &str, cli: &Cli) -> Result<String, GmhError> {
+    let (subject, body) = split_message(message);
+    if cli.subject_only {
+        return Ok(subject.to_string());
+    }
+    if !cli.body_only {
+        return Ok(message.to_string());
+    }
+    if body.is_empty() {
+        return Err(GmhError::Config("the generated message has no body to keep for --body-only".to_string()));
+    }
+    Ok(match &cli.subject {
+        Some(subject) => format!("{}\n\n{}", subject.trim(), body),
+        None => body.to_string(),
+    })
 }
-#[derive(Deserialize, Debug)]
-struct Choice {
-    index: u32,
-    message: MessageResponse,
-    logprobs: Option<serde_json::Value>, // 可以是 null，所以用 Option
-    finish_reason: String,
+
+// 只做宽松的校验, 具体格式交给 git
+fn parse_author(value: &str) -> Result<String, String> {
+    match (value.find('<'), value.rfind('>')) {
+        (Some(start), Some(end)) if start < end => Ok(value.to_string()),
+        _ => Err("expected \"Name <email>\"".to_string()),
+    }
 }
 
-#[derive(Deserialize, Debug)]
-struct MessageResponse {
-    #[allow(dead_code)]
-    role: String,
-    content: String,
+// 在第一个冒号处分开, 两边去掉空白; 名字和值不合法时在发请求之前就报错
+fn parse_header(value: &str) -> Result<(String, String), String> {
+    let (name, value) = value.split_once(':').ok_or("expected \"Key: Value\"")?;
+    let (name, value) = (name.trim(), value.trim());
+    reqwest::header::HeaderName::from_bytes(name.as_bytes())
+        .map_err(|_| format!("invalid header name '{}'", name))?;
+    reqwest::header::HeaderValue::from_str(value).map_err(|_| format!("invalid value for header '{}'", name))?;
+    Ok((name.to_string(), value.to_string()))
 }
 
-async fn get_git_diff() -> Result<String, String> {
-    let output = Command::new("git")
-        .arg("diff")
-        .arg("--cached")
-        .output()
-        .map_err(|e| e.to_string())?;
+// 以 - 开头的值会被 git 当成选项
+fn parse_ref(value: &str) -> Result<String, String> {
+    if value.is_empty() || value.starts_with('-') {
+        return Err("expected a branch, tag or commit".to_string());
+    }
+    Ok(value.to_string())
+}
 
-    if output.status.success() {
-        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+// 所有提交共用的 git commit 参数
+fn commit_args(cli: &Cli, quiet: bool) -> Vec<String> {
+    let mut args = Vec::new();
+    if cli.no_verify {
+        args.push("--no-verify".to_string());
+    }
+    if quiet {
+        args.push("--quiet".to_string());
+    }
+    if let Some(author) = &cli.author {
+        args.push(format!("--author={}", author));
+    }
+    if let Some(date) = &cli.date {
+        args.push(format!("--date={}", date));
+    }
+    match cli.gpg_sign.as_deref() {
+        Some("") => args.push("-S".to_string()),
+        Some(key) => args.push(format!("--gpg-sign={}", key)),
+        None => {}
+    }
+    args
+}
+
+// 没有配置签名的 key 时 git 的报错很简短, 补上怎么解决
+fn explain_signing_error(err: GmhError) -> GmhError {
+    const SIGNING_ERRORS: &[&str] = &["gpg failed to sign", "cannot run gpg", "No secret key", "user.signingkey"];
+    match err {
+        GmhError::GitCommand(reason) if SIGNING_ERRORS.iter().any(|e| reason.contains(e)) => {
+            GmhError::GitCommand(format!(
+                "{}\ncould not sign the commit; set a key with `git config user.signingkey <KEYID>` \
+                 or pass --gpg-sign=<KEYID>, and check that gpg can find it (gpg --list-secret-keys)",
+                reason.trim_end()
+            ))
+        }
+        err => err,
+    }
+}
+
+// 编辑器在前台时 Ctrl-C 归编辑器处理 (例如 vi 里取消当前命令), 不退出 gmh
+static EDITOR_RUNNING: AtomicBool = AtomicBool::new(false);
+
+// 在单独的任务里等 Ctrl-C, 主任务阻塞在 read_line 或等待 API 时也能响应.
+// 直接退出会丢弃进行中的请求; git commit 本身是原子的, 不会留下一半的提交
+fn handle_ctrl_c() {
+    tokio::spawn(async {
+        loop {
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+            if EDITOR_RUNNING.load(Ordering::SeqCst) {
+                continue;
+            }
+            // 清掉 spinner 或者输入到一半的行
+            if std::io::stderr().is_terminal() {
+                eprint!("\r\x1b[2K");
+            }
+            // 写到 stderr, --output json 时 stdout 上只有结果
+            eprintln!("Canceled.");
+            process::exit(130);
+        }
+    });
+}
+
+fn default_editor() -> &'static str {
+    if cfg!(windows) {
+        "notepad"
     } else {
-        Err(String::from_utf8_lossy(&output.stderr).to_string())
+        "vi"
     }
 }
 
-async fn generate_commit_message(diff: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let api_key = env::var("OPENAI_API_KEY").expect("OPENAI_API_KEY not set in .env file");
-    let client = Client::new();
+// 把消息写到临时文件, 用 $EDITOR 打开, 返回编辑后的内容
+fn edit_message(message: &str) -> Result<String, GmhError> {
+    let path = git_path("GMH_EDITMSG")?;
+    let comment = comment_char(git_config("core.commentChar").ok().as_deref(), message);
+    let template = format!(
+        "{}\n\n{c} Edit the commit message above. Lines starting with '{c}' will be ignored,\n\
+         {c} and an empty message aborts the commit.\n",
+        message.trim(),
+        c = comment
+    );
+    fs::write(&path, template)?;
 
-    let request_body = DeepSeekRequest {
-        model: "deepseek-chat".to_string(),
-        messages: vec![
-            Message {
-                role: "system".to_string(),
-                content: "You are a helpful assistant to great a short git commit message.(Short output, only keep the git commit message)".to_string(),
-            },
-            Message {
-                role: "user".to_string(),
-                content: diff.to_string(),
-            },
-        ],
-        stream: false,
+    let editor = env::var("EDITOR")
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+        .unwrap_or_else(|| default_editor().to_string());
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or(default_editor());
+
+    EDITOR_RUNNING.store(true, Ordering::SeqCst);
+    let status = Command::new(program).args(parts).arg(&path).status();
+    EDITOR_RUNNING.store(false, Ordering::SeqCst);
+    let edited = fs::read_to_string(&path);
+    let _ = fs::remove_file(&path);
+
+    let status = status.map_err(|e| GmhError::Editor(format!("failed to launch editor '{}': {}", editor, e)))?;
+    if !status.success() {
+        return Err(GmhError::Editor(format!("editor '{}' exited with {}", editor, status)));
+    }
+
+    Ok(strip_comments(&edited?, &comment))
+}
+
+// 通过 shell 运行, 可以用管道和参数, 例如 `fold -s -w 72`; 失败时带上命令的 stderr
+fn postprocess(command: &str, message: &str) -> Result<String, GmhError> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let mut child = shell
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| GmhError::Postprocess(format!("cannot run '{}': {}", command, e)))?;
+    // 写完就关掉 stdin, 命令才能读到 EOF; 命令不读 stdin 时写入会失败, 以退出码为准
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(message.as_bytes());
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(GmhError::Postprocess(format!("'{}' exited with {}\n{}", command, output.status, stderr)));
+    }
+    let message = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if message.is_empty() {
+        return Err(GmhError::Postprocess(format!("'{}' printed an empty message", command)));
+    }
+    Ok(message)
+}
+
+// 提示写到 stderr, stdout 上只有消息本身
+fn copy_message(message: &str) -> Result<(), GmhError> {
+    let program = clipboard::copy(message)?;
+    eprintln!("Copied the commit message to the clipboard ({}); nothing was committed.", program);
+    Ok(())
+}
+
+// 每条问题一行, 格式和编译器一样是 file:line: rule: message
+fn lint_message(cli: &Cli, path: &Path) -> Result<(), GmhError> {
+    let settings = Settings::resolve(cli, config::load_config(repo_root().as_deref())?)?;
+    let text = fs::read_to_string(path).map_err(|source| GmhError::File {
+        path: path.display().to_string(),
+        source,
+    })?;
+    let text = normalize_newlines(&text);
+    let rules = lint::Rules {
+        max_subject_length: settings.max_subject_length.unwrap_or(SUBJECT_MAX_LEN),
+        conventional: settings.conventional,
+        scope: settings.scope.clone(),
     };
+    let comment = comment_char(git_config("core.commentChar").ok().as_deref(), &text);
+    let problems = lint::check(&text, &comment, &rules);
+    for problem in &problems {
+        eprintln!("{}:{}: {}: {}", path.display(), problem.line, problem.rule, problem.message);
+    }
+    match problems.len() {
+        0 => Ok(()),
+        n => Err(GmhError::LintFailed(n)),
+    }
+}
 
-    // let json_body = serde_json::to_string_pretty(&request_body).expect("Failed to serialize request body");
-    // println!("Request body (JSON):\n{}", json_body);
+// 用 rev-parse 找 hooks 目录, 兼容 worktree 和 core.hooksPath
+fn install_hook(force: bool) -> Result<(), GmhError> {
+    let output = run_git(["rev-parse", "--git-path", "hooks/prepare-commit-msg"])?;
+    if !output.status.success() {
+        return Err(GmhError::GitCommand(String::from_utf8_lossy(&output.stderr).to_string()));
+    }
+    let path = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+
+    if let Ok(existing) = fs::read_to_string(&path) {
+        if !existing.contains(HOOK_MARKER) && !force {
+            return Err(GmhError::Config(format!(
+                "{} already exists and was not installed by gmh; pass --force to overwrite it",
+                path.display()
+            )));
+        }
+    }
 
-    let response = client
-        .post("https://api.deepseek.com/chat/completions")
-        .header("Authorization", format!("Bearer {}", api_key))
-        .header("Content-Type", "application/json")
-        .json(&request_body)
-        .send()
-        .await?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, HOOK_SCRIPT)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+    }
 
+    println!("Installed prepare-commit-msg hook at {}", path.display());
+    Ok(())
+}
 
-    let response_body: DeepSeekResponse = response.json().await?;
+// git 以外部消息 (-m/-F)、merge、squash、amend 调用 hook 时不覆盖用户的消息
+fn hook_should_generate(source: Option<&str>) -> bool {
+    matches!(source, None | Some("") | Some("template"))
+}
 
-    // 提取助手的回复
-    if let Some(choice) = response_body.choices.first() {
-        Ok(choice.message.content.clone())
+// 把生成的消息放在文件开头, 保留 git 写入的注释.
+// 模型已经按 commit.template 填好了内容时, git 预先写入的模板只留注释行, 免得重复
+fn write_hook_message(path: &Path, message: &str, filled_template: bool) -> Result<(), GmhError> {
+    let existing = fs::read_to_string(path).unwrap_or_default();
+    let existing = if filled_template {
+        let comment = comment_char(git_config("core.commentChar").ok().as_deref(), &existing);
+        let comments: String = existing
+            .lines()
+            .filter(|line| line.starts_with(comment.as_str()))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        format!("\n{}", comments)
     } else {
-        Err("No response from DeepSeek".into())
+        existing
+    };
+    fs::write(path, format!("{}\n{}", message.trim(), existing)).map_err(|source| GmhError::File {
+        path: path.display().to_string(),
+        source,
+    })
+}
+
+fn print_usage(generation: &Generation) {
+    let Some(usage) = &generation.usage else {
+        eprintln!("tokens: usage not reported");
+        return;
+    };
+    let price = |var: &str| env::var(var).ok().and_then(|v| v.parse::<f64>().ok());
+    match (price("GMH_PROMPT_PRICE"), price("GMH_COMPLETION_PRICE")) {
+        (Some(prompt), Some(completion)) => {
+            eprintln!("{} (~${:.6})", usage, usage.cost(prompt, completion))
+        }
+        _ => eprintln!("{}", usage),
     }
 }
 
-async fn commit_changes(commit_message: &str) -> Result<(), String> {
-    let status = Command::new("git")
-        .arg("commit")
-        .arg("-m")
-        .arg(commit_message)
-        .status()
-        .map_err(|e| e.to_string())?;
+// 等待 API 时在 stderr 上显示进度和耗时, 完成后清掉这一行
+async fn with_spinner<F: std::future::Future>(enabled: bool, future: F) -> F::Output {
+    if !enabled {
+        return future.await;
+    }
+    tokio::pin!(future);
+    let start = Instant::now();
+    let mut ticker = tokio::time::interval(Duration::from_millis(100));
+    let mut frame = 0;
+    let output = loop {
+        tokio::select! {
+            biased;
+            output = &mut future => break output,
+            _ = ticker.tick() => {
+                let mut stderr = std::io::stderr();
+                let _ = write!(
+                    stderr,
+                    "\r{} Generating commit message... {}s",
+                    SPINNER_FRAMES[frame % SPINNER_FRAMES.len()],
+                    start.elapsed().as_secs()
+                );
+                let _ = stderr.flush();
+                frame += 1;
+            }
+        }
+    };
+    eprint!("\r\x1b[2K");
+    output
+}
 
-    if status.success() {
+// 给每次请求加上终端上的反馈: 等待时的 spinner, 流式输出的收尾, -v 时的用量.
+// 交给 pipeline::refine 时重试的请求也一样显示
+#[derive(Clone, Copy)]
+struct Console<'a> {
+    provider: &'a dyn Provider,
+    spinner: bool,
+    verbose: bool,
+}
+
+#[async_trait]
+impl Provider for Console<'_> {
+    async fn generate(&self, diff: &str, opts: &GenerateOptions) -> Result<Generation, GmhError> {
+        let result = with_spinner(self.spinner && !opts.stream, self.provider.generate(diff, opts)).await;
+        if opts.stream {
+            println!();
+            if let Ok(generation) = &result {
+                if std::io::stdout().is_terminal() {
+                    rerender(&generation.message);
+                }
+            }
+        }
+        if let (true, Ok(generation)) = (self.verbose, &result) {
+            print_usage(generation);
+        }
+        result
+    }
+}
+
+// 流式输出的片段直接打印
+fn print_chunk(chunk: &str) {
+    print!("{}", chunk);
+    let _ = std::io::stdout().flush();
+}
+
+// 流式输出的片段可能带着多余的空行和空白, 结束后擦掉输出的区域, 再整齐地打印一次完整的消息.
+// 区域超出一屏时已经滚动出去的部分擦不掉, 这时保持原样
+fn rerender(message: &str) {
+    let Ok((columns, rows)) = ratatui::crossterm::terminal::size() else {
+        return;
+    };
+    let columns = usize::from(columns.max(1));
+    let printed: usize = message
+        .split('\n')
+        .map(|line| line.chars().count().div_ceil(columns).max(1))
+        .sum();
+    if printed == 0 || printed >= usize::from(rows) || message.trim().is_empty() {
+        return;
+    }
+    // 光标移到区域第一行的行首, 清除到屏幕末尾
+    println!("\x1b[{}F\x1b[J{}", printed, message.trim());
+}
+
+// 先用 `n` 参数请求, provider 不支持或返回不够时再并发补发请求, 最多同时 concurrency 个.
+// 补发的请求部分失败时保留成功的候选, 只有全部失败才报错
+async fn generate_candidates(
+    console: &Console<'_>,
+    diff: &str,
+    opts: &GenerateOptions,
+    concurrency: usize,
+) -> Result<Vec<Generation>, GmhError> {
+    let wanted = opts.candidates as usize;
+    let mut generation = console.generate(diff, opts).await?;
+    let alternatives = std::mem::take(&mut generation.alternatives);
+    let mut candidates = Vec::with_capacity(wanted);
+    candidates.push(generation);
+    candidates.extend(alternatives);
+
+    let missing = wanted.saturating_sub(candidates.len());
+    if missing > 0 {
+        let single = GenerateOptions {
+            candidates: 1,
+            ..opts.clone()
+        };
+        // 并发的请求共用一个 spinner
+        let quiet = Console { spinner: false, ..*console };
+        let requests = stream::iter((0..missing).map(|_| quiet.generate(diff, &single))).buffered(concurrency);
+        let results: Vec<_> = with_spinner(console.spinner, requests.collect()).await;
+        let mut errors = Vec::new();
+        for result in results {
+            match result {
+                Ok(generation) => candidates.push(generation),
+                Err(err) => errors.push(err),
+            }
+        }
+        if let Some(err) = errors.first() {
+            eprintln!("Warning: {} of {} candidate requests failed: {}", errors.len(), missing, err);
+        }
+    }
+    candidates.truncate(wanted);
+    Ok(candidates)
+}
+
+// TUI 里重新生成和按要求修改的消息和第一次一样经过检查、截断和格式化, 同时返回是否被截断
+async fn regenerate(
+    console: &Console<'_>,
+    diff: &str,
+    opts: &mut GenerateOptions,
+    checks: &pipeline::Checks,
+    settings: &Settings,
+    cli: &Cli,
+) -> Result<(String, bool), GmhError> {
+    let generation = console.generate(diff, opts).await?;
+    let generation = pipeline::refine(console, diff, opts, generation, checks, false).await?;
+    let mut message = limit_words(generation.message, settings);
+    if settings.max_subject_length.is_none() {
+        warn_long_subject(&message);
+    }
+    message = finish_message(&message, settings);
+    Ok((select_part(&message, cli)?, generation.truncated))
+}
+
+fn print_json<T: Serialize>(output: &T) -> Result<(), GmhError> {
+    println!("{}", serde_json::to_string(output)?);
+    Ok(())
+}
+
+fn print_split(commits: &[split::SplitCommit]) {
+    for (i, commit) in commits.iter().enumerate() {
+        let message: Vec<String> = commit
+            .message
+            .lines()
+            .map(|line| if line.is_empty() { String::new() } else { format!("    {}", line) })
+            .collect();
+        println!("[{}] {}", i + 1, message.join("\n").trim_start());
+        println!("    files: {}", commit.files.join(", "));
+    }
+}
+
+// git commit 成功时的输出 (提交摘要、钩子的输出) 照原样显示
+fn print_git_output(output: &Output) {
+    print!("{}", String::from_utf8_lossy(&output.stdout));
+    eprint!("{}", String::from_utf8_lossy(&output.stderr));
+}
+
+fn print_candidates(candidates: &[String]) {
+    for (i, candidate) in candidates.iter().enumerate() {
+        println!("[{}] {}", i + 1, candidate.trim().replace('\n', "\n    "));
+    }
+}
+
+// y/yes 确认, 大小写不限; 空输入在 default_yes 时也算确认, 其他回答都是取消
+fn is_yes(answer: &str, default_yes: bool) -> bool {
+    match answer.trim().to_lowercase().as_str() {
+        "" => default_yes,
+        "y" | "yes" => true,
+        _ => false,
+    }
+}
+
+// 提示里大写的选项是直接回车时的默认值
+fn yes_no(default_yes: bool) -> &'static str {
+    if default_yes {
+        "Y/n"
+    } else {
+        "y/n"
+    }
+}
+
+// 读一行回答; 读到 EOF (管道里没有输入) 时不当作 n, 单独报错
+fn read_answer() -> Result<String, GmhError> {
+    let mut input = String::new();
+    if std::io::stdin().read_line(&mut input)? == 0 {
+        return Err(GmhError::NoInput);
+    }
+    Ok(input)
+}
+
+fn confirm_split(count: usize, default_yes: bool) -> Result<(), GmhError> {
+    println!("Create these {} commits? ({})", count, yes_no(default_yes));
+    if is_yes(&read_answer()?, default_yes) {
         Ok(())
     } else {
-        Err("Failed to commit changes".to_string())
+        Err(GmhError::Canceled("Commit canceled."))
     }
 }
 
-fn is_git_repository() -> bool {
-    Path::new(".git").exists()
+// 暂存会改变索引, 要先确认, 不受 --default-yes 影响
+fn confirm_stage(command: &str) -> Result<(), GmhError> {
+    println!("Stage changes with `{}` before generating? (y/n)", command);
+    if is_yes(&read_answer()?, false) {
+        Ok(())
+    } else {
+        Err(GmhError::Canceled("Staging canceled."))
+    }
+}
+
+// 输入编号选择候选, 空输入取消, 超出范围时重新询问
+fn select_candidate(candidates: Vec<String>) -> Result<String, GmhError> {
+    loop {
+        println!("Select a message to commit (1-{}), or press Enter to cancel:", candidates.len());
+        let input = read_answer()?;
+        if input.trim().is_empty() {
+            return Err(GmhError::Canceled("Commit canceled."));
+        }
+        match input.trim().parse::<usize>() {
+            Ok(index) if (1..=candidates.len()).contains(&index) => {
+                return Ok(candidates.into_iter().nth(index - 1).expect("index checked above"));
+            }
+            _ => println!("Invalid selection '{}'.", input.trim()),
+        }
+    }
 }
 
+// --yes 时不提示; 没有 --yes 时只有 stdin 是终端才能提示
+fn should_prompt(yes: bool, stdin_is_tty: bool) -> bool {
+    !yes && stdin_is_tty
+}
+
+enum Confirmation {
+    Commit(String),
+    // r:<instruction>, 带着之前的对话重新生成
+    Refine(String),
+}
+
+// 询问是否提交, 返回最终要提交的消息或者修改的要求.
+// y/yes 提交, e/edit 编辑, r:<instruction> 修改, 其他回答取消; default_yes 时空输入也提交
+fn confirm(commit_message: &str, prompt: Option<&str>, default_yes: bool) -> Result<Confirmation, GmhError> {
+    match prompt {
+        Some(prompt) => println!("{}", prompt),
+        None => println!(
+            "Do you want to commit these changes? ({}/e, or r:<instruction> to refine)",
+            yes_no(default_yes)
+        ),
+    }
+    let input = read_answer()?;
+    if let Some(instruction) = input.trim().strip_prefix("r:").map(str::trim) {
+        if !instruction.is_empty() {
+            return Ok(Confirmation::Refine(instruction.to_string()));
+        }
+    }
+    match input.trim().to_lowercase().as_str() {
+        answer if is_yes(answer, default_yes) => Ok(Confirmation::Commit(commit_message.to_string())),
+        "e" | "edit" => {
+            let message = edit_message(commit_message)?;
+            if message.is_empty() {
+                return Err(GmhError::Canceled("Aborting commit due to empty commit message."));
+            }
+            Ok(Confirmation::Commit(message))
+        }
+        _ => Err(GmhError::Canceled("Commit canceled.")),
+    }
+}
+
+// 默认只显示 warn, -v 是 info, -vv 是 debug, -vvv 是 trace
+fn init_logger(verbose: u8) {
+    let level = match verbose {
+        0 => "warn",
+        1 => "info",
+        2 => "debug",
+        _ => "trace",
+    };
+    // 库里的警告 (重试、finish_reason 等) 和以前一样显示成 Warning: ...
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(format!("gmh={}", level)))
+        .format(|buf, record| match record.level() {
+            log::Level::Warn => writeln!(buf, "Warning: {}", record.args()),
+            level => writeln!(buf, "[{:<5} {}] {}", level, record.target(), record.args()),
+        })
+        .init();
+}
 
 #[tokio::main]
 async fn main() {
-    if !is_git_repository() {
-        eprintln!("Current directory is not a Git repository.");
-        return;
+    let cli = Cli::parse();
+    init_logger(cli.verbose);
+    handle_ctrl_c();
+
+    match run(cli).await {
+        Ok(()) => {}
+        Err(err @ GmhError::Canceled(_)) => {
+            println!("{}", err);
+            process::exit(err.exit_code());
+        }
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            process::exit(err.exit_code());
+        }
     }
+}
 
-    dotenv().ok(); // 加载 .env 文件
+async fn run(cli: Cli) -> Result<(), GmhError> {
+    let json = cli.output == OutputFormat::Json;
+    let verbose = cli.verbose > 0;
+    // 外部 diff 和暂存区无关, 只打印不提交; json 模式下没有 --yes 也只打印
+    let dry_run = cli.dry_run || cli.copy || cli.diff_file.is_some() || (json && !cli.yes);
+    let hook_file = cli.hook.then(|| PathBuf::from(&cli.hook_args[0]));
 
-    // 获取 git diff
-    let diff = match get_git_diff().await {
-        Ok(diff) => diff,
-        Err(err) => {
-            eprintln!("Error getting git diff: {}", err);
-            return;
+    // init 和 doctor 不需要在 git 仓库里运行
+    if let Some(Commands::Init) = cli.command {
+        return init::run();
+    }
+    // 自己检查是否在仓库里, 不在时也要打印其他检查的结果
+    if let Some(Commands::Doctor) = cli.command {
+        return doctor::run(&cli).await;
+    }
+    if let Some(Commands::Lint { file }) = &cli.command {
+        return lint_message(&cli, file);
+    }
+
+    if cli.diff_file.is_none() && !is_git_repository()? {
+        return Err(GmhError::NotAGitRepo);
+    }
+
+    if let Some(Commands::InstallHook { force }) = cli.command {
+        return install_hook(force);
+    }
+    if cli.hook && !hook_should_generate(cli.hook_args.get(1).map(String::as_str)) {
+        return Ok(());
+    }
+    // 合并等操作进行中时 diff 里是合并进来的全部改动, 用 git 准备好的消息;
+    // rebase 中途 --amend (edit) 是正常的用法
+    if cli.diff_file.is_none() && cli.command.is_none() {
+        match operation_in_progress()? {
+            Some(GitOperation::Rebase) if cli.amend => {}
+            Some(operation) => {
+                if !cli.hook {
+                    let name = operation.name();
+                    eprintln!(
+                        "A {} is in progress; keeping git's message. Run `git commit` or `git {} --continue`.",
+                        name, name
+                    );
+                }
+                return Ok(());
+            }
+            None => {}
         }
+    }
+
+    config::load_env_files(&cli.env_file)?;
+
+    let root = repo_root();
+    let settings = Settings::resolve(&cli, config::load_config(root.as_deref())?)?;
+    let http = HttpOptions {
+        timeout: settings.timeout,
+        max_retries: settings.max_retries,
+        max_wait: settings.max_wait,
+        proxy: cli.proxy.clone(),
+        client: None,
+        api_key: None,
+        headers: cli.headers.clone(),
+        dump_request: cli.dump_request.clone(),
+        dump_response: cli.dump_response.clone(),
+    };
+    let provider = provider::build(settings.provider, settings.api_base.as_deref(), http)?;
+    // json 模式下 stderr 也可能被脚本读取, 不显示 spinner
+    let console = Console {
+        provider: provider.as_ref(),
+        spinner: !json && std::io::stderr().is_terminal(),
+        verbose,
+    };
+    let system_prompt = match &settings.system_prompt {
+        Some(prompt) => prompt.clone(),
+        None => settings.format.system_prompt(settings.conventional),
+    };
+    let system_prompt = match &settings.scope {
+        Some(scope) => format!("{}\nUse `{}` as the scope: `type({}): subject`.", system_prompt, scope, scope),
+        None => system_prompt,
+    };
+    let system_prompt = if settings.gitmoji {
+        format!("{}\n{}", system_prompt, gitmoji::SYSTEM_PROMPT_HINT)
+    } else {
+        system_prompt.to_string()
+    };
+    let system_prompt = match settings.lang.as_deref().map(language_name) {
+        Some("English") | None => system_prompt,
+        Some(language) => format!("{}\nWrite the commit message in {}.", system_prompt, language),
+    };
+    let system_prompt = match settings.max_words {
+        Some(max_words) => format!("{}\nLimit the entire message to about {} words.", system_prompt, max_words),
+        None => system_prompt,
+    };
+    // 默认的 prompt 只要一行, --body-only 时要求写正文
+    let system_prompt = if cli.body_only && settings.format == MessageFormat::Short && settings.system_prompt.is_none() {
+        format!("{}\n{}", system_prompt, BODY_HINT)
+    } else {
+        system_prompt
+    };
+    let template = if settings.commit_template { commit_template() } else { None };
+    let system_prompt = match &template {
+        Some(template) => {
+            let comment = comment_char(git_config("core.commentChar").ok().as_deref(), template);
+            format!("{}\n{}", system_prompt, template_instruction(template, &comment))
+        }
+        None => system_prompt,
+    };
+    // 分支名里带 issue 编号时让模型加上 Closes #N
+    let issue = match (&settings.issue_pattern, &cli.diff_file) {
+        (Some(pattern), None) => current_branch().ok().and_then(|branch| issue_from_branch(&branch, pattern)),
+        _ => None,
+    };
+    let system_prompt = match &issue {
+        Some(issue) => format!(
+            "{}\nThis change resolves issue #{}; end the message with a `Closes #{}` line after a blank line.",
+            system_prompt, issue, issue
+        ),
+        None => system_prompt,
+    };
+    let on_chunk: OnChunk = Arc::new(Mutex::new(print_chunk));
+    let mut opts = GenerateOptions {
+        model: settings.model.clone(),
+        system_prompt,
+        stream: settings.stream,
+        // 固定 seed 时温度为 0, 否则 seed 也不能保证结果一样
+        temperature: Some(if cli.seed.is_some() { 0.0 } else { settings.temperature }),
+        max_tokens: settings.max_tokens,
+        seed: cli.seed,
+        candidates: if cli.hook { 1 } else { cli.candidates },
+        context: cli.context.clone(),
+        history: Vec::new(),
+        on_chunk: Some(on_chunk),
+    };
+    // hook 模式下终端归 git 管, json 模式下 stdout 只留给结果, 都不输出流式内容.
+    // --offline 时没有可以流式输出的内容, stdout 不是终端 (CI、管道) 时流式输出只会留下碎片
+    if cli.hook || json || cli.offline || !std::io::stdout().is_terminal() {
+        opts.stream = false;
+    }
+
+    // 只有 body 时没有标题, 不能提交
+    if cli.body_only && cli.subject.is_none() && !dry_run && !cli.hook {
+        return Err(GmhError::Config(
+            "--body-only needs --subject to commit; pass --subject \"...\" or --dry-run".to_string(),
+        ));
+    }
+
+    // 暂存区有改动时 amend 会把它们一起带进去
+    if cli.amend && !cli.force && has_staged_changes()? {
+        return Err(GmhError::GitCommand(
+            "there are staged changes that would be included in the amended commit; unstage them or pass --force"
+                .to_string(),
+        ));
+    }
+
+    // 重新生成时参考原来的消息, 不至于和原来的意图相差太远
+    if cli.amend {
+        let message = last_commit_message()?;
+        if !message.is_empty() {
+            opts.context.push(format!("{}\n\n{}", AMEND_HINT, message));
+        }
+    }
+
+    // 和 git commit -a 一样先暂存, 再取 diff
+    if cli.stage_all || cli.stage_tracked {
+        let flag = if cli.stage_all { "-A" } else { "-u" };
+        if should_prompt(cli.yes, std::io::stdin().is_terminal()) {
+            confirm_stage(&format!("git add {}", flag))?;
+        } else if !cli.yes {
+            return Err(GmhError::Canceled(
+                "stdin is not a terminal; pass --yes to stage without confirmation. Staging canceled.",
+            ));
+        }
+        stage_changes(flag, &cli.paths)?;
+    }
+    // `git commit -- <paths>` 提交的是工作区内容, 和 --split 一样, 未暂存的改动会被一起带进去
+    if cli.only && !dry_run && !cli.hook && unstaged_file_count(&cli.paths)? > 0 {
+        return Err(GmhError::GitCommand(
+            "some --path files also have unstaged changes that --only would commit; stash or stage them first"
+                .to_string(),
+        ));
+    }
+
+    let since = match &cli.command {
+        Some(Commands::Summarize { since }) => Some(since.as_str()),
+        _ => None,
+    };
+
+    // 获取 git diff
+    let diff_options = DiffOptions {
+        find_copies: cli.find_copies,
+        algorithm: settings.diff_algorithm,
+        context_lines: settings.context_lines,
+    };
+    let diff = match &cli.diff_file {
+        Some(path) => read_diff_file(path)?,
+        None => match since {
+            Some(since) => get_range_diff(since, &cli.paths, &diff_options).await?,
+            None if cli.amend => get_last_commit_diff(&diff_options).await?,
+            None => get_git_diff(&cli.paths, &diff_options).await?,
+        },
     };
 
     if diff.is_empty() {
-        println!("No changes detected.");
-        return;
+        let unstaged = if cli.diff_file.is_none() && !cli.amend && since.is_none() {
+            unstaged_file_count(&cli.paths)?
+        } else {
+            0
+        };
+        let notice = if unstaged > 0 {
+            format!("No staged changes. Did you forget to 'git add'? ({} files modified)", unstaged)
+        } else {
+            "No changes detected.".to_string()
+        };
+        if json {
+            eprintln!("{}", notice);
+        } else {
+            println!("{}", notice);
+        }
+        return Ok(());
     }
 
-    // 生成 commit 消息
-    let commit_message = match generate_commit_message(&diff).await {
-        Ok(message) => message,
-        Err(err) => {
-            eprintln!("Error generating commit message: {}", err);
-            return;
+    // 未跟踪的文件拼成新文件形式的 diff, 和暂存的改动一样经过下面的 ignore 和大小限制
+    let mut diff = diff;
+    let untracked = if cli.include_untracked { untracked_files(&cli.paths)? } else { Vec::new() };
+    for path in &untracked {
+        diff.push_str(&untracked_diff(path)?);
+    }
+    // 二进制文件的段落会被下面换成一行说明, 所以在这之前统计
+    let mut file_stats = if cli.offline || settings.offline_fallback {
+        diff::file_stats(&diff)
+    } else {
+        Vec::new()
+    };
+
+    // 子模块的更新只有两个 SHA, 把子模块里对应的提交列表交给模型
+    if cli.diff_file.is_none() {
+        let root = root.as_deref().unwrap_or(Path::new("."));
+        for bump in diff::submodule_bumps(&diff) {
+            let short = |sha: &str| sha.chars().take(7).collect::<String>();
+            match submodule_log(&root.join(&bump.path), &bump.old, &bump.new) {
+                Some(log) => opts.context.push(format!(
+                    "Submodule {} was updated from {} to {}, which includes these commits:\n{}",
+                    bump.path,
+                    short(&bump.old),
+                    short(&bump.new),
+                    log.trim_end()
+                )),
+                None => log::info!("cannot list the commits of submodule {}, it is not checked out", bump.path),
+            }
+        }
+    }
+
+    // 外部 diff 没有对应的 git 状态, 不显示 stat
+    if (cli.show_diff || verbose) && cli.diff_file.is_none() && since.is_none() {
+        eprint!("{}", get_diff_stat(&cli.paths, cli.amend)?);
+    }
+
+    let diff = diff::summarize_binary(&diff);
+
+    let ignore = diff::load_ignore(
+        root.as_deref().unwrap_or(Path::new(".")),
+        &settings.ignore,
+        !cli.no_default_ignore,
+    )?;
+    let (diff, excluded) = diff::exclude_files(&diff, &ignore);
+    if verbose && !excluded.is_empty() {
+        eprintln!("Excluded from the diff: {}", excluded.join(", "));
+    }
+    file_stats.retain(|stat| !excluded.contains(&stat.path));
+    let untracked: Vec<&str> = untracked
+        .iter()
+        .map(String::as_str)
+        .filter(|path| !excluded.iter().any(|e| e == path))
+        .collect();
+    if !untracked.is_empty() {
+        log::info!("untracked files: {}", untracked.join(", "));
+        opts.context.push(format!(
+            "The diff also includes untracked files that are not staged yet: {}",
+            untracked.join(", ")
+        ));
+    }
+
+    // 在发给第三方之前检查, 离线或只统计 token 时不会发送
+    if !cli.allow_secrets && !cli.offline && !cli.count_tokens {
+        let found = secrets::scan(&diff);
+        for secret in &found {
+            eprintln!("Possible secret: {} in {}:{}", secret.kind, secret.path, secret.line);
+        }
+        if !found.is_empty() {
+            return Err(GmhError::SecretsFound(found.len()));
         }
+    }
+
+    log::info!("provider {:?}, model {}", settings.provider, settings.model);
+    log::info!("diff: {} bytes, ~{} tokens", diff.len(), diff::estimate_tokens(&diff));
+
+    // 在截断之前统计, 放在 diff 最前面
+    let languages = if cli.lang_hint { diff::language_summary(&diff) } else { None };
+    let (diff, truncated) = diff::truncate_diff(&diff, settings.max_diff_tokens);
+    if truncated {
+        eprintln!(
+            "Warning: diff exceeds {} tokens, some lines were omitted.",
+            settings.max_diff_tokens
+        );
+    }
+    let diff = match languages {
+        Some(languages) => {
+            log::info!("{}", languages);
+            format!("{}\n\n{}", languages, diff)
+        }
+        None => diff,
     };
 
-    println!("Generated commit message:\n{}", commit_message);
+    // 模板代替内置的 system prompt 和 diff 消息, 只用于生成提交消息
+    let diff = match (&cli.template, &cli.command) {
+        (Some(path), None) => {
+            let template = fs::read_to_string(path).map_err(|source| GmhError::File {
+                path: path.display().to_string(),
+                source,
+            })?;
+            // 外部 diff 没有分支和 stat
+            let in_repo = cli.diff_file.is_none();
+            let branch = if in_repo && template.contains("{{branch}}") { current_branch()? } else { String::new() };
+            let stat = if in_repo && template.contains("{{stat}}") {
+                get_diff_stat(&cli.paths, cli.amend)?
+            } else {
+                String::new()
+            };
+            let lang = settings.lang.as_deref().map_or("English", language_name);
+            opts.system_prompt = String::new();
+            render_prompt_template(
+                &template,
+                &[("diff", &diff), ("branch", &branch), ("stat", stat.trim_end()), ("lang", lang)],
+            )
+        }
+        _ => diff,
+    };
 
-    println!("Do you want to commit these changes? (y/n)");
-    let mut input = String::new();
-    std::io::stdin().read_line(&mut input).expect("Failed to read input");
+    // 按实际会发送的消息估算, 不调用 API
+    if cli.count_tokens {
+        let messages = provider::chat_messages(&diff, &opts);
+        let tokens = diff::estimate_message_tokens(messages.iter().map(|m| m.content.as_str()));
+        if json {
+            print_json(&serde_json::json!({ "prompt_tokens": tokens, "model": settings.model }))?;
+        } else {
+            println!("~{} prompt tokens (estimated at 4 characters per token)", tokens);
+        }
+        return Ok(());
+    }
+
+    // 只换 system prompt, diff 的处理和 provider 都和生成提交消息一样, 不碰仓库
+    if let Some(Commands::Explain) = cli.command {
+        if cli.offline {
+            return Err(GmhError::Config("gmh explain needs the API and cannot be used with --offline".to_string()));
+        }
+        opts.system_prompt = match settings.lang.as_deref().map(language_name) {
+            Some("English") | None => EXPLAIN_SYSTEM_PROMPT.to_string(),
+            Some(language) => format!("{}\nAnswer in {}.", EXPLAIN_SYSTEM_PROMPT, language),
+        };
+        opts.candidates = 1;
+        let generation = console.generate(&diff, &opts).await?;
+        if !opts.stream {
+            println!("{}", generation.message.trim());
+        }
+        return Ok(());
+    }
+
+    // 和 explain 一样只打印, commit 列表放在 diff 后面, 帮助模型理解每一步的意图
+    if let Some(since) = since {
+        if cli.offline {
+            return Err(GmhError::Config("gmh summarize needs the API and cannot be used with --offline".to_string()));
+        }
+        opts.system_prompt = match settings.lang.as_deref().map(language_name) {
+            Some("English") | None => SUMMARIZE_SYSTEM_PROMPT.to_string(),
+            Some(language) => format!("{}\nAnswer in {}.", SUMMARIZE_SYSTEM_PROMPT, language),
+        };
+        if cli.diff_file.is_none() {
+            opts.context.insert(0, format!("Commits since {}:\n{}", since, commit_log(since)?));
+        }
+        opts.candidates = 1;
+        let generation = console.generate(&diff, &opts).await?;
+        if !opts.stream {
+            println!("{}", generation.message.trim());
+        }
+        return Ok(());
+    }
+
+    let prefix = settings.prefix.clone();
+    let suffix = settings.suffix.clone();
+    let wrap = |message: &str| -> Result<String, GmhError> {
+        if prefix.is_none() && suffix.is_none() {
+            return Ok(message.to_string());
+        }
+        let uses_branch = [&prefix, &suffix]
+            .iter()
+            .any(|t| t.as_deref().is_some_and(|t| t.contains("{branch}")));
+        let branch = if uses_branch { current_branch()? } else { String::new() };
+        Ok(apply_template(message, prefix.as_deref(), suffix.as_deref(), &branch))
+    };
+
+    let print_result = |message: &str, model: &str, usage: Option<&Usage>, candidates: &[String]| {
+        print_json(&JsonOutput {
+            message,
+            model,
+            provider: settings.provider,
+            usage,
+            candidates,
+        })
+    };
+
+    // 多个候选或拆分提交时不使用流式输出, 列出来让用户选
+    // 全屏界面接管终端时也不输出流式内容
+    let tui = cli.tui && std::io::stdin().is_terminal() && std::io::stdout().is_terminal();
+    if opts.candidates > 1 || cli.split || cli.sweep || tui {
+        opts.stream = false;
+    }
 
-    if input.trim().to_lowercase() == "y" {
-        if let Err(err) = commit_changes(&commit_message).await {
-            eprintln!("Error committing changes: {}", err);
+    // 只用来比较不同温度下的输出, 从不提交
+    if cli.sweep {
+        let mut results = Vec::with_capacity(SWEEP_TEMPERATURES.len());
+        for temperature in SWEEP_TEMPERATURES {
+            opts.temperature = Some(temperature);
+            let generation = console.generate(&diff, &opts).await?;
+            if !json {
+                println!("[temperature {:.1}]\n{}\n", temperature, generation.message.trim());
+            }
+            results.push(JsonSweepResult {
+                temperature,
+                message: generation.message,
+                usage: generation.usage,
+            });
+        }
+        if json {
+            print_json(&results)?;
+        }
+        return Ok(());
+    }
+
+    if cli.split {
+        // `git commit -- <files>` 提交的是工作区内容, 这些文件有未暂存的改动时会被一起带进去
+        let diff_files: Vec<String> = diff::files(&diff).into_iter().map(String::from).collect();
+        if !dry_run && unstaged_file_count(&diff_files)? > 0 {
+            return Err(GmhError::GitCommand(
+                "some staged files also have unstaged changes that --split would commit; stash or stage them first"
+                    .to_string(),
+            ));
+        }
+        opts.system_prompt = format!("{}\n{}", opts.system_prompt, split::SYSTEM_PROMPT_HINT);
+        let generation = console.generate(&diff, &opts).await?;
+        let commits = split::parse(&generation.message, &diff::files(&diff))?;
+        if json {
+            print_json(&JsonSplitOutput {
+                commits: &commits,
+                model: &settings.model,
+                provider: settings.provider,
+                usage: generation.usage.as_ref(),
+            })?;
+        } else {
+            print_split(&commits);
+        }
+        if dry_run {
+            return Ok(());
+        }
+
+        if should_prompt(cli.yes, std::io::stdin().is_terminal()) {
+            confirm_split(commits.len(), settings.default_yes)?;
+        } else if !cli.yes {
+            return Err(GmhError::Canceled(
+                "stdin is not a terminal; pass --yes to create the commits. Commit canceled.",
+            ));
+        }
+
+        for commit in &commits {
+            let mut message = wrap(&commit.message)?;
+            if let Some(command) = &settings.postprocess {
+                message = postprocess(command, &message)?;
+            }
+            if cli.signoff {
+                let (name, email) = (git_config("user.name")?, git_config("user.email")?);
+                message = append_signoff(&message, &name, &email);
+            }
+            let output = commit_changes(&message, &commit.files, &commit_args(&cli, json))
+                .await
+                .map_err(explain_signing_error)?;
+            print_git_output(&output);
+        }
+        if json {
+            eprintln!("Created {} commits.", commits.len());
+        } else {
+            println!("Created {} commits.", commits.len());
+        }
+        return Ok(());
+    }
+
+    // 续写、换模型和各种检查后的重试在库里, 缓存和离线的消息不再请求
+    let checks = pipeline::Checks {
+        min_message_length: settings.min_message_length,
+        conventional: settings.conventional,
+        gitmoji: settings.gitmoji,
+        retry_empty: settings.retry_empty,
+        max_subject_length: settings.max_subject_length,
+        model_fallback: settings.model_fallback.clone(),
+        emoji: settings.emoji,
+    };
+    let commit_message = if opts.candidates > 1 {
+        let generations = generate_candidates(&console, &diff, &opts, cli.concurrency as usize).await?;
+        // 每个候选都和单条消息一样检查和重试, 并发的请求共用一个 spinner
+        let quiet = Console { spinner: false, ..console };
+        let single = GenerateOptions {
+            candidates: 1,
+            ..opts.clone()
+        };
+        let refined = stream::iter(generations.into_iter().map(|generation| {
+            let mut opts = single.clone();
+            let (diff, checks) = (&diff, &checks);
+            async move { pipeline::refine(&quiet, diff, &mut opts, generation, checks, false).await }
+        }))
+        .buffered(cli.concurrency as usize);
+        let refined: Vec<_> = with_spinner(console.spinner, refined.collect()).await;
+        let mut usage = None;
+        let mut candidates = Vec::with_capacity(refined.len());
+        for (i, generation) in refined.into_iter().enumerate() {
+            let generation = generation?;
+            if generation.truncated {
+                eprintln!("Warning: candidate [{}] was cut off at the token limit and is incomplete.", i + 1);
+            }
+            add_usage(&mut usage, generation.usage);
+            candidates.push(finish_message(&limit_words(generation.message, &settings), &settings));
+        }
+        if json {
+            // json 模式下没有交互, 提交时取第一个
+            let first = candidates.first().ok_or(GmhError::NoChoices)?;
+            print_result(&wrap(first)?, &opts.model, usage.as_ref(), &candidates)?;
+            if dry_run {
+                return Ok(());
+            }
+            candidates.into_iter().next().ok_or(GmhError::NoChoices)?
+        } else {
+            print_candidates(&candidates);
+            if dry_run {
+                return Ok(());
+            }
+
+            if cli.yes {
+                candidates.into_iter().next().ok_or(GmhError::NoChoices)?
+            } else if std::io::stdin().is_terminal() {
+                select_candidate(candidates)?
+            } else {
+                return Err(GmhError::Canceled(
+                    "stdin is not a terminal; pass --yes to commit the first candidate. Commit canceled.",
+                ));
+            }
+        }
+    } else {
+        // 生成 commit 消息
+        if opts.stream && !dry_run {
+            println!("Generated commit message:");
+        }
+        let cache_key =
+            (!cli.no_cache).then(|| cache::key(settings.provider, settings.api_base.as_deref(), &opts, &diff));
+        let cached = cache_key.as_deref().and_then(cache::get);
+        let mut offline = cli.offline && cached.is_none();
+        let generation = match cached.clone() {
+            Some(message) => {
+                log::debug!("cache hit {}", cache_key.as_deref().unwrap_or_default());
+                if verbose {
+                    eprintln!("(cached)");
+                }
+                // 命中缓存时没有流式输出, 直接打印
+                if opts.stream {
+                    println!("{}", message);
+                }
+                Generation::new(message, None)
+            }
+            None if offline => Generation::new(offline_message(&file_stats), None),
+            None => match console.generate(&diff, &opts).await {
+                // 下面会重新生成
+                Err(GmhError::EmptyMessage) if settings.retry_empty => Generation::new(String::new(), None),
+                Err(err) if settings.offline_fallback && err.is_offline() => {
+                    eprintln!("Warning: {}\nUsing a message built from the diff stat instead.", err);
+                    offline = true;
+                    let message = offline_message(&file_stats);
+                    if opts.stream {
+                        println!("{}", message);
+                    }
+                    Generation::new(message, None)
+                }
+                result => result?,
+            },
+        };
+
+        let generation =
+            pipeline::refine(&console, &diff, &mut opts, generation, &checks, offline || cached.is_some()).await?;
+        let mut commit_message = generation.message;
+        let usage = generation.usage;
+        let mut truncated = generation.truncated;
+        // 缓存模型写的消息, scope、换行等本地的处理每次按当前的设置重新做
+        if let (Some(key), None, false, false) = (&cache_key, &cached, offline, truncated) {
+            cache::put(key, &commit_message);
+        }
+        commit_message = limit_words(commit_message, &settings);
+        if settings.max_subject_length.is_none() {
+            warn_long_subject(&commit_message);
+        }
+        commit_message = finish_message(&commit_message, &settings);
+
+        if truncated {
+            eprintln!("Warning: the message was cut off at the token limit and is incomplete.");
+        }
+        commit_message = select_part(&commit_message, &cli)?;
+
+        if let Some(path) = &hook_file {
+            let filled_template = template.is_some() && cli.hook_args.get(1).map(String::as_str) == Some("template");
+            let mut message = wrap(&commit_message)?;
+            if let Some(command) = &settings.postprocess {
+                message = postprocess(command, &message)?;
+            }
+            return write_hook_message(path, &message, filled_template);
+        }
+
+        if json {
+            print_result(&wrap(&commit_message)?, &opts.model, usage.as_ref(), &[])?;
+            if cli.copy {
+                copy_message(&wrap(&commit_message)?)?;
+            }
+            if dry_run {
+                return Ok(());
+            }
+            // 没有人确认, 不提交不完整的消息
+            if truncated {
+                return Err(GmhError::Truncated);
+            }
+            commit_message
         } else {
-            println!("Changes committed successfully.");
+            // dry-run 只输出消息本身, 方便脚本使用
+            if dry_run {
+                if !opts.stream {
+                    println!("{}", wrap(&commit_message)?);
+                }
+                if cli.copy {
+                    copy_message(&wrap(&commit_message)?)?;
+                }
+                return Ok(());
+            }
+
+            if tui {
+                let stat = match &cli.diff_file {
+                    Some(_) => String::new(),
+                    None => get_diff_stat(&cli.paths, cli.amend)?,
+                };
+                loop {
+                    match tui::review(&stat, &commit_message)? {
+                        tui::Action::Accept(message) => break message,
+                        tui::Action::Regenerate => {
+                            (commit_message, truncated) =
+                                regenerate(&console, &diff, &mut opts, &checks, &settings, &cli).await?;
+                            if truncated {
+                                eprintln!("Warning: the message was cut off at the token limit and is incomplete.");
+                            }
+                        }
+                    }
+                }
+            } else {
+                if !opts.stream {
+                    println!("Generated commit message:\n{}", commit_message);
+                }
+
+                if should_prompt(cli.yes, std::io::stdin().is_terminal()) {
+                    loop {
+                        let prompt = settings.confirm_prompt.as_deref();
+                        let instruction = match confirm(&commit_message, prompt, settings.default_yes)? {
+                            Confirmation::Commit(message) => break message,
+                            Confirmation::Refine(instruction) => instruction,
+                        };
+                        // 之前的回复和修改要求作为新的一轮对话, diff 不变可以命中 prompt 缓存
+                        opts.history.push(Message::new("assistant", &commit_message));
+                        opts.history.push(Message::new("user", &instruction));
+                        if opts.stream {
+                            println!("Generated commit message:");
+                        }
+                        (commit_message, truncated) =
+                            regenerate(&console, &diff, &mut opts, &checks, &settings, &cli).await?;
+                        if truncated {
+                            eprintln!("Warning: the message was cut off at the token limit and is incomplete.");
+                        }
+                        if !opts.stream {
+                            println!("Generated commit message:\n{}", commit_message);
+                        }
+                    }
+                } else if cli.yes && truncated {
+                    return Err(GmhError::Truncated);
+                } else if cli.yes {
+                    commit_message
+                } else {
+                    // 非交互环境下不阻塞在 read_line 上
+                    return Err(GmhError::Canceled(
+                        "stdin is not a terminal; pass --yes to commit without confirmation. Commit canceled.",
+                    ));
+                }
+            }
         }
+    };
+
+    if commit_message.trim().is_empty() {
+        return Err(GmhError::EmptyMessage);
+    }
+
+    // 在编辑之后再加前缀/后缀, 避免被编辑时误删
+    let mut commit_message = wrap(&commit_message)?;
+    if let Some(command) = &settings.postprocess {
+        commit_message = postprocess(command, &commit_message)?;
+    }
+    if cli.signoff {
+        let (name, email) = (git_config("user.name")?, git_config("user.email")?);
+        commit_message = append_signoff(&commit_message, &name, &email);
+    }
+    let commit_paths: &[String] = if cli.only { &cli.paths } else { &[] };
+    let mut args = commit_args(&cli, json);
+    if cli.amend {
+        args.insert(0, "--amend".to_string());
+    }
+    let output = commit_changes(&commit_message, commit_paths, &args)
+        .await
+        .map_err(explain_signing_error)?;
+    print_git_output(&output);
+    if json {
+        eprintln!("Changes committed successfully.");
     } else {
-        println!("Commit canceled.");
+        println!("Changes committed successfully.");
     }
-}
\ No newline at end of file
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prompts_only_without_yes_on_a_terminal() {
+        assert!(should_prompt(false, true));
+        assert!(!should_prompt(true, true));
+        // 管道和 CI 里不等待输入
+        assert!(!should_prompt(false, false));
+        assert!(!should_prompt(true, false));
+    }
+}
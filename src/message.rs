@@ -0,0 +1,481 @@
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::diff::{FileStat, FileStatus};
+
+pub const DEFAULT_SYSTEM_PROMPT: &str = "You are a helpful assistant to generate a short git commit message.(Short output, only keep the git commit message)";
+pub const CONVENTIONAL_SYSTEM_PROMPT: &str = "You are a helpful assistant to generate a short git commit message following the Conventional Commits specification: `type(scope): subject`, where scope is optional. (Short output, only keep the git commit message)";
+pub const DETAILED_SYSTEM_PROMPT: &str = "You are a helpful assistant to generate a git commit message: a short summary line, a blank line, then a bulleted body of `- ` items explaining what changed and why. (Only output the git commit message)";
+pub const CHANGELOG_SYSTEM_PROMPT: &str = "You are a helpful assistant to generate a git commit message written as a changelog entry: a short summary line, a blank line, then the changes as `- ` items grouped under `### Added`, `### Changed` and `### Fixed` headings, leaving out empty groups. (Only output the git commit message)";
+pub const EXPLAIN_SYSTEM_PROMPT: &str = "You are a helpful assistant that explains code changes. Given a git diff, explain in plain English what this change does and why, in a few short paragraphs. Do not write a commit message.";
+pub const SUMMARIZE_SYSTEM_PROMPT: &str = "You are a helpful assistant that summarizes a series of commits, for example for a pull request description. Given the commit list and the combined git diff, write a summary in a few short paragraphs: what changed overall, why, and anything reviewers should pay attention to. Do not list every commit and do not write a commit message.";
+pub const BODY_HINT: &str = "Write a short subject line, a blank line, then a body explaining what changed and why.";
+pub const AMEND_HINT: &str = "This diff is being amended into an existing commit. Its current message is below; update it to match the diff, keeping its intent and wording where they still apply.";
+pub const CONTINUE_HINT: &str = "Your message was cut off. Continue exactly where it stopped, without repeating anything.";
+pub const RETRY_EMPTY_HINT: &str = "Be specific about what changed and why.";
+pub const CONVENTIONAL_SUMMARY_HINT: &str = "The summary line must follow the Conventional Commits specification: `type(scope): subject`, where scope is optional.";
+pub const CONVENTIONAL_STRICT_HINT: &str = "The first line MUST match `type(scope): subject` exactly, with no quotes, markdown or extra text before it.";
+
+// --format 的预设, short 就是原来的一行消息
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageFormat {
+    #[default]
+    Short,
+    Detailed,
+    Changelog,
+}
+
+impl MessageFormat {
+    // detailed 和 changelog 在 conventional 时要求第一行是 `type(scope): subject`, 避免第一次请求必然不合格
+    pub fn system_prompt(self, conventional: bool) -> String {
+        let prompt = match self {
+            MessageFormat::Short if conventional => return CONVENTIONAL_SYSTEM_PROMPT.to_string(),
+            MessageFormat::Short => DEFAULT_SYSTEM_PROMPT,
+            MessageFormat::Detailed => DETAILED_SYSTEM_PROMPT,
+            MessageFormat::Changelog => CHANGELOG_SYSTEM_PROMPT,
+        };
+        if conventional {
+            format!("{}\n{}", prompt, CONVENTIONAL_SUMMARY_HINT)
+        } else {
+            prompt.to_string()
+        }
+    }
+}
+
+// 允许的 Conventional Commits type
+pub const CONVENTIONAL_TYPES: &[&str] = &[
+    "feat", "fix", "docs", "style", "refactor", "perf", "test", "build", "ci", "chore", "revert",
+];
+
+pub const SUBJECT_MAX_LEN: usize = 50;
+pub const BODY_WIDTH: usize = 72;
+
+pub fn conventional_regex() -> Regex {
+    // 允许前面带一个 gitmoji (shortcode 或 emoji)
+    Regex::new(&format!(
+        r"^(?:(?::[a-z0-9_+-]+:|[^\x00-\x7F]+) )?({})(\([^()]+\))?!?: .+",
+        CONVENTIONAL_TYPES.join("|")
+    ))
+        .expect("invalid conventional commit regex")
+}
+
+// 只校验第一行 (subject)
+pub fn is_conventional(message: &str) -> bool {
+    let subject = message.trim().lines().next().unwrap_or("");
+    conventional_regex().is_match(subject)
+}
+
+// scope 会放进 `type(scope):`, 不能有空白和括号
+pub fn validate_scope(scope: &str) -> Result<String, String> {
+    if scope.is_empty() || scope.contains(|c: char| c.is_whitespace() || c == '(' || c == ')') {
+        return Err(format!("scope must be non-empty without spaces or parentheses, got '{}'", scope));
+    }
+    Ok(scope.to_string())
+}
+
+// 把 subject 的 scope 换成指定的, 没有 scope 时加上; 不是 Conventional Commit 时原样返回
+pub fn set_scope(message: &str, scope: &str) -> String {
+    let message = message.trim();
+    let (subject, rest) = message.split_once('\n').unwrap_or((message, ""));
+    let Some(captures) = conventional_regex().captures(subject) else {
+        return message.to_string();
+    };
+    let kind = captures.get(1).expect("type is not optional");
+    let end = captures.get(2).map_or(kind.end(), |old| old.end());
+    let subject = format!("{}({}){}", &subject[..kind.end()], scope, &subject[end..]);
+    if rest.is_empty() {
+        subject
+    } else {
+        format!("{}\n{}", subject, rest)
+    }
+}
+
+// 常见语言代码换成全称, 模型更容易理解; 其他值原样使用
+pub fn language_name(code: &str) -> &str {
+    match code.to_ascii_lowercase().as_str() {
+        "en" => "English",
+        "ja" => "Japanese",
+        "zh" | "zh-cn" => "Simplified Chinese",
+        "zh-tw" => "Traditional Chinese",
+        "ko" => "Korean",
+        "es" => "Spanish",
+        "de" => "German",
+        "fr" => "French",
+        "pt" => "Portuguese",
+        "ru" => "Russian",
+        "it" => "Italian",
+        _ => code,
+    }
+}
+
+// Windows 上 git 和模型都可能输出 CRLF, 统一成 \n 之后再按行处理
+pub fn normalize_newlines(text: &str) -> String {
+    text.replace("\r\n", "\n")
+}
+
+// 按第一个空行分成标题和正文
+pub fn split_message(message: &str) -> (&str, &str) {
+    let message = message.trim();
+    match message.split_once("\n\n") {
+        Some((subject, body)) => (subject.trim_end(), body.trim_matches('\n')),
+        None => (message, ""),
+    }
+}
+
+// subject 是第一个换行之前的内容, 按字符计数
+pub fn subject_len(message: &str) -> usize {
+    message.trim().lines().next().unwrap_or_default().chars().count()
+}
+
+// 在单词边界把 subject 截到 max_len 个字符以内, 整个单词都超长时按字符截断, 正文不变
+pub fn truncate_subject(message: &str, max_len: usize) -> String {
+    let message = message.trim();
+    let (subject, rest) = match message.split_once('\n') {
+        Some((subject, rest)) => (subject, Some(rest)),
+        None => (message, None),
+    };
+    let end = match subject.char_indices().nth(max_len) {
+        Some((end, _)) => end,
+        None => return message.to_string(),
+    };
+    let cut = &subject[..end];
+    // 下一个字符是空白时 cut 本身就落在单词边界上
+    let cut = if subject[end..].starts_with(char::is_whitespace) {
+        cut
+    } else {
+        cut.rfind(char::is_whitespace).filter(|&i| i > 0).map_or(cut, |i| &cut[..i])
+    };
+    match rest {
+        Some(rest) => format!("{}\n{}", cut.trim_end(), rest),
+        None => cut.trim_end().to_string(),
+    }
+}
+
+// 保留前 max_words 个单词, 单词之间原有的空格和换行不变
+pub fn truncate_words(text: &str, max_words: usize) -> String {
+    let mut words = 0;
+    let mut in_word = false;
+    for (i, c) in text.char_indices() {
+        if c.is_whitespace() {
+            in_word = false;
+        } else if !in_word {
+            if words == max_words {
+                return text[..i].trim_end().to_string();
+            }
+            in_word = true;
+            words += 1;
+        }
+    }
+    text.trim_end().to_string()
+}
+
+// subject 保持不变, 正文截到整个消息大约 max_words 个单词
+pub fn truncate_body_words(message: &str, max_words: usize) -> String {
+    let (subject, body) = split_message(message);
+    let body = truncate_words(body, max_words.saturating_sub(subject.split_whitespace().count()));
+    if body.is_empty() {
+        subject.to_string()
+    } else {
+        format!("{}\n\n{}", subject, body)
+    }
+}
+
+// 保留原有换行, 代码块和缩进的行原样保留, 超长的单词 (如 URL) 不拆开
+pub fn wrap_body(text: &str, width: usize) -> String {
+    let mut lines = Vec::new();
+    let mut in_code_block = false;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+            lines.push(line.to_string());
+            continue;
+        }
+        if in_code_block
+            || line.starts_with("    ")
+            || line.starts_with('\t')
+            || line.chars().count() <= width
+        {
+            lines.push(line.to_string());
+            continue;
+        }
+
+        // 列表项的续行和内容对齐
+        let content = line.trim_start();
+        let indent = &line[..line.len() - content.len()];
+        let marker = ["- ", "* ", "+ "]
+            .iter()
+            .find(|marker| content.starts_with(*marker))
+            .map_or(0, |marker| marker.len());
+        let hang = " ".repeat(indent.len() + marker);
+
+        let mut current = indent.to_string();
+        let mut empty = true;
+        for word in content.split_whitespace() {
+            if !empty && current.chars().count() + 1 + word.chars().count() > width {
+                lines.push(current);
+                current = hang.clone();
+                empty = true;
+            }
+            if !empty {
+                current.push(' ');
+            }
+            current.push_str(word);
+            empty = false;
+        }
+        lines.push(current);
+    }
+    lines.join("\n")
+}
+
+// 标题保持不变, 只折正文
+pub fn wrap_message(message: &str) -> String {
+    let (subject, body) = split_message(message);
+    if body.is_empty() {
+        return message.to_string();
+    }
+    format!("{}\n\n{}", subject, wrap_body(body, BODY_WIDTH))
+}
+
+// 最后一段全是 `Token: value` (或 `Closes #N`) 形式时, 新的 trailer 直接接在后面
+fn ends_with_trailers(message: &str) -> bool {
+    let Some((_, last)) = message.rsplit_once("\n\n") else {
+        return false;
+    };
+    let re = Regex::new(r"^([A-Za-z][A-Za-z0-9-]*: |(Closes|Fixes|Resolves) #)").expect("invalid trailer regex");
+    last.lines().all(|line| re.is_match(line))
+}
+
+// trailer 和正文之间空一行, 已经有相同的 trailer 时不重复添加
+pub fn append_trailer(message: &str, trailer: &str) -> String {
+    let message = message.trim_end();
+    if message.lines().any(|line| line.trim() == trailer) {
+        return message.to_string();
+    }
+    let sep = if ends_with_trailers(message) { "\n" } else { "\n\n" };
+    format!("{}{}{}", message, sep, trailer)
+}
+
+pub fn append_signoff(message: &str, name: &str, email: &str) -> String {
+    append_trailer(message, &format!("Signed-off-by: {} <{}>", name, email))
+}
+
+// 分支名里的 issue 编号, 只认开头的编号 (feature/123-add-thing) 和 issue-45 / gh-45,
+// 像 release/2024-10-15 这样的日期和版本号不算
+pub const DEFAULT_ISSUE_PATTERN: &str = r"(?i)(?:^|/)#?(\d+)-[a-z]|(?:^|[/_-])(?:issue|gh)[-_#]?(\d+)(?:[/_-]|$)";
+
+// 取第一个匹配到的捕获组, 没有捕获组时取整个匹配
+pub fn issue_from_branch(branch: &str, pattern: &Regex) -> Option<String> {
+    let caps = pattern.captures(branch)?;
+    let issue = caps.iter().skip(1).flatten().next().or_else(|| caps.get(0))?.as_str();
+    Some(issue.trim_start_matches('#').to_string()).filter(|issue| !issue.is_empty())
+}
+
+// 给消息加上前缀/后缀, 没有空白分隔时自动补一个空格
+pub fn apply_template(message: &str, prefix: Option<&str>, suffix: Option<&str>, branch: &str) -> String {
+    let mut result = message.trim().to_string();
+    if let Some(prefix) = prefix.filter(|p| !p.is_empty()) {
+        let prefix = prefix.replace("{branch}", branch);
+        let sep = if prefix.ends_with(char::is_whitespace) { "" } else { " " };
+        result = format!("{}{}{}", prefix, sep, result);
+    }
+    if let Some(suffix) = suffix.filter(|s| !s.is_empty()) {
+        let suffix = suffix.replace("{branch}", branch);
+        let sep = if suffix.starts_with(char::is_whitespace) { "" } else { " " };
+        result = format!("{}{}{}", result, sep, suffix);
+    }
+    result
+}
+
+// 替换 --template 里的 {{name}}, 不认识的占位符原样保留
+pub fn render_prompt_template(template: &str, vars: &[(&str, &str)]) -> String {
+    vars.iter().fold(template.to_string(), |text, (name, value)| {
+        text.replace(&format!("{{{{{}}}}}", name), value)
+    })
+}
+
+// core.commentChar=auto 时 git 依次尝试的字符
+const AUTO_COMMENT_CHARS: &str = "#;@!$%^&|:";
+
+// 和 git 一样, 去掉以 comment_char 开头的注释行
+pub fn strip_comments(text: &str, comment_char: &str) -> String {
+    text.lines()
+        .filter(|line| !line.starts_with(comment_char))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+// 根据 core.commentChar 的值选注释字符, 未设置时是 #, auto 时选一个不在消息行首出现的字符
+pub fn comment_char(config: Option<&str>, message: &str) -> String {
+    match config {
+        Some("auto") => AUTO_COMMENT_CHARS
+            .chars()
+            .find(|c| !message.lines().any(|line| line.trim_start().starts_with(*c)))
+            .unwrap_or('#')
+            .to_string(),
+        Some(value) if !value.is_empty() => value.to_string(),
+        _ => "#".to_string(),
+    }
+}
+
+// commit.template 作为骨架交给模型, 注释行是写给人看的说明
+pub fn template_instruction(template: &str, comment_char: &str) -> String {
+    format!(
+        "The repository's commit template is below. Follow its structure: put the subject and body where the template \
+         expects them and fill in its sections. Lines starting with `{}` are instructions, do not copy them into the \
+         message.\n\n{}",
+        comment_char,
+        template.trim_end()
+    )
+}
+
+// 所有路径共同的目录, 没有时 (或都在根目录下) 返回 None
+fn common_dir<'a>(paths: impl Iterator<Item = &'a str>) -> Option<String> {
+    let mut common: Option<Vec<&str>> = None;
+    for path in paths {
+        let dirs: Vec<&str> = path.split('/').collect();
+        let dirs = &dirs[..dirs.len() - 1];
+        common = Some(match common {
+            None => dirs.to_vec(),
+            Some(common) => common.iter().zip(dirs).take_while(|(a, b)| a == b).map(|(a, _)| *a).collect(),
+        });
+    }
+    common.filter(|dirs| !dirs.is_empty()).map(|dirs| dirs.join("/"))
+}
+
+// 不调用 API 时根据 diff stat 拼出的消息, 例如 `Add src/offline.rs`、`Update 3 files (+20/-4) in src`
+pub fn offline_message(stats: &[FileStat]) -> String {
+    let stat = match stats {
+        [] => return "Update files".to_string(),
+        [stat] => stat,
+        _ => return offline_summary(stats),
+    };
+    match &stat.status {
+        FileStatus::Added => format!("Add {}", stat.path),
+        FileStatus::Deleted => format!("Remove {}", stat.path),
+        FileStatus::Renamed(from) if stat.added == 0 && stat.removed == 0 => format!("Rename {} to {}", from, stat.path),
+        _ => format!("Update {} (+{}/-{})", stat.path, stat.added, stat.removed),
+    }
+}
+
+// 多个文件时只说数量和行数, 都是新增 (或都是删除) 时用 Add (Remove)
+fn offline_summary(stats: &[FileStat]) -> String {
+    let verb = if stats.iter().all(|s| s.status == FileStatus::Added) {
+        "Add"
+    } else if stats.iter().all(|s| s.status == FileStatus::Deleted) {
+        "Remove"
+    } else {
+        "Update"
+    };
+    let added: usize = stats.iter().map(|s| s.added).sum();
+    let removed: usize = stats.iter().map(|s| s.removed).sum();
+    let mut message = format!("{} {} files (+{}/-{})", verb, stats.len(), added, removed);
+    if let Some(dir) = common_dir(stats.iter().map(|s| s.path.as_str())) {
+        message.push_str(&format!(" in {}", dir));
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_format_asks_for_conventional_commits_when_enabled() {
+        for format in [MessageFormat::Short, MessageFormat::Detailed, MessageFormat::Changelog] {
+            assert!(format.system_prompt(true).contains("`type(scope): subject`"), "{:?}", format);
+            assert!(!format.system_prompt(false).contains("Conventional Commits"), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn default_issue_pattern_skips_dates_and_versions() {
+        let pattern = Regex::new(DEFAULT_ISSUE_PATTERN).unwrap();
+        let issue = |branch| issue_from_branch(branch, &pattern);
+        assert_eq!(issue("feature/123-add-thing").as_deref(), Some("123"));
+        assert_eq!(issue("fix-issue-45").as_deref(), Some("45"));
+        assert_eq!(issue("GH-7").as_deref(), Some("7"));
+        assert_eq!(issue("release/2024-10-15"), None);
+        assert_eq!(issue("release/1.2.3"), None);
+        assert_eq!(issue("v2-migration"), None);
+    }
+
+    #[test]
+    fn wrap_body_keeps_line_breaks_and_code_blocks() {
+        let long = "one two three four five six seven eight nine ten eleven twelve";
+        let body = format!("short line\n\n- {}\n```\n{}\n```\n    {}", long, long, long);
+        assert_eq!(
+            wrap_body(&body, 30),
+            format!(
+                "short line\n\n- one two three four five six\n  seven eight nine ten eleven\n  twelve\n```\n{}\n```\n    {}",
+                long, long
+            )
+        );
+        // 超长的单词不拆开
+        let url = "https://example.com/a/very/long/path/that/does/not/fit";
+        assert_eq!(wrap_body(&format!("see {}", url), 20), format!("see\n{}", url));
+    }
+
+    #[test]
+    fn truncate_subject_counts_characters_not_bytes() {
+        // 在单词边界截断, 正文不变
+        assert_eq!(truncate_subject("Añadir soporte para árboles\n\nbody", 16), "Añadir soporte\n\nbody");
+        // 没有空格的文字按字符截断, 不会切到字符中间
+        assert_eq!(truncate_subject("修复登录页面的超时问题", 6), "修复登录页面");
+        assert_eq!(truncate_subject("🐛 fix crash", 5), "🐛 fix");
+        assert_eq!(truncate_subject("🐛 fix crash", 4), "🐛");
+        assert_eq!(truncate_subject("修复登录", 10), "修复登录");
+    }
+
+    #[test]
+    fn truncate_words_keeps_the_original_spacing() {
+        assert_eq!(truncate_words("one  two\nthree four", 3), "one  two\nthree");
+        assert_eq!(truncate_words("one two", 5), "one two");
+        assert_eq!(truncate_words("one two", 0), "");
+    }
+
+    #[test]
+    fn truncate_body_words_keeps_the_subject() {
+        let message = "Fix the login timeout\n\nThe session expired too early because the clock was wrong.";
+        // subject 占了 4 个词, 正文剩下 2 个
+        assert_eq!(truncate_body_words(message, 6), "Fix the login timeout\n\nThe session");
+        // subject 已经超过时只留 subject
+        assert_eq!(truncate_body_words(message, 2), "Fix the login timeout");
+    }
+
+    #[test]
+    fn offline_message_describes_the_diff_stat() {
+        let stat = |path: &str, status: FileStatus, added, removed| FileStat {
+            path: path.to_string(),
+            status,
+            added,
+            removed,
+        };
+        assert_eq!(offline_message(&[]), "Update files");
+        assert_eq!(offline_message(&[stat("src/offline.rs", FileStatus::Added, 40, 0)]), "Add src/offline.rs");
+        assert_eq!(offline_message(&[stat("old.txt", FileStatus::Deleted, 0, 3)]), "Remove old.txt");
+        assert_eq!(
+            offline_message(&[stat("b.rs", FileStatus::Renamed("a.rs".to_string()), 0, 0)]),
+            "Rename a.rs to b.rs"
+        );
+        assert_eq!(offline_message(&[stat("README.md", FileStatus::Modified, 2, 1)]), "Update README.md (+2/-1)");
+        assert_eq!(
+            offline_message(&[
+                stat("src/diff.rs", FileStatus::Modified, 10, 2),
+                stat("src/git/mod.rs", FileStatus::Added, 10, 2),
+            ]),
+            "Update 2 files (+20/-4) in src"
+        );
+    }
+
+    #[test]
+    fn crlf_messages_split_like_lf_messages() {
+        let reply = "Fix the parser\r\n\r\nHandle CRLF input.\r\nKeep LF as is.\r\n";
+        let message = normalize_newlines(reply);
+        assert_eq!(message, "Fix the parser\n\nHandle CRLF input.\nKeep LF as is.\n");
+        assert_eq!(split_message(&message), ("Fix the parser", "Handle CRLF input.\nKeep LF as is."));
+        assert_eq!(subject_len(&message), 14);
+    }
+}
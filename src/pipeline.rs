@@ -0,0 +1,133 @@
+use crate::error::GmhError;
+use crate::gitmoji;
+use crate::message::{
+    is_conventional, subject_len, truncate_subject, CONTINUE_HINT, CONVENTIONAL_STRICT_HINT, RETRY_EMPTY_HINT,
+};
+use crate::provider::{GenerateOptions, Generation, Message, Provider, Usage};
+
+// 被截断时最多请模型接着写几次
+pub const MAX_CONTINUATIONS: usize = 2;
+
+// 第一次生成之后的检查, 不合格时重新请求
+pub struct Checks {
+    pub min_message_length: usize,
+    pub conventional: bool,
+    // 和 conventional 一起, 重试时要求带 gitmoji
+    pub gitmoji: bool,
+    pub retry_empty: bool,
+    pub max_subject_length: Option<usize>,
+    // 不合格时换这个模型再试一次, 之后的重试也用它
+    pub model_fallback: Option<String>,
+    // 在检查 subject 长度之前把 shortcode 换成 emoji
+    pub emoji: bool,
+}
+
+// 多次请求时累加用量
+pub fn add_usage(total: &mut Option<Usage>, usage: Option<Usage>) {
+    match (total.as_mut(), usage) {
+        (Some(total), Some(usage)) => *total += usage,
+        (None, usage) => *total = usage,
+        (Some(_), None) => {}
+    }
+}
+
+// 依次处理截断、换模型、空消息、Conventional Commits 和 subject 长度, 需要时重新请求.
+// offline 时 (离线生成或命中缓存) 不再请求, 只做本地的处理.
+// 重试时对 opts 的修改会保留, 之后的修改和重新生成沿用同样的模型和 prompt
+pub async fn refine(
+    provider: &dyn Provider,
+    diff: &str,
+    opts: &mut GenerateOptions,
+    mut generation: Generation,
+    checks: &Checks,
+    offline: bool,
+) -> Result<Generation, GmhError> {
+    // 在 max_tokens 处被截断时, 把已有的部分作为回复, 请模型接着写
+    let mut continuations = 0;
+    while !offline && generation.truncated && continuations < MAX_CONTINUATIONS {
+        log::warn!("message was cut off at the token limit, asking the model to finish it...");
+        let mut continue_opts = opts.clone();
+        continue_opts.history.push(Message::new("assistant", &generation.message));
+        continue_opts.history.push(Message::new("user", CONTINUE_HINT));
+        let rest = provider.generate(diff, &continue_opts).await?;
+        generation.message.push_str(&rest.message);
+        add_usage(&mut generation.usage, rest.usage);
+        generation.truncated = rest.truncated;
+        continuations += 1;
+    }
+
+    // 便宜的模型写得不合格时换 model_fallback 再试一次
+    let passes_checks = |message: &str| {
+        message.trim().chars().count() >= checks.min_message_length && (!checks.conventional || is_conventional(message))
+    };
+    if let Some(fallback) = checks.model_fallback.as_ref().filter(|_| !offline) {
+        if !passes_checks(&generation.message) {
+            log::warn!("message from {} did not pass the checks, retrying with {}...", opts.model, fallback);
+            opts.model = fallback.clone();
+            let retry = provider.generate(diff, opts).await?;
+            replace(&mut generation, retry);
+        }
+    }
+    if !offline {
+        log::info!("message generated by {}", opts.model);
+    }
+
+    // 空消息或者太短 (例如只有 "Update") 时要求写具体一点, 再试一次.
+    // 离线的消息不会因为重试变好, 下面的重试都跳过
+    if !offline && checks.retry_empty && generation.message.trim().chars().count() < checks.min_message_length {
+        log::warn!("message is empty or too short, retrying...");
+        opts.system_prompt = format!("{}\n{}", opts.system_prompt, RETRY_EMPTY_HINT);
+        let retry = provider.generate(diff, opts).await?;
+        replace(&mut generation, retry);
+    }
+
+    // 不符合 Conventional Commits 时用更严格的 prompt 重试一次
+    if !offline && checks.conventional && !is_conventional(&generation.message) {
+        log::warn!("message is not a Conventional Commit, retrying with a stricter prompt...");
+        opts.system_prompt = format!("{}\n{}", opts.system_prompt, CONVENTIONAL_STRICT_HINT);
+        if checks.gitmoji {
+            opts.system_prompt = format!("{}\n{}", opts.system_prompt, gitmoji::STRICT_HINT);
+        }
+        match provider.generate(diff, opts).await {
+            Ok(retry) => replace(&mut generation, retry),
+            Err(err) => log::warn!("error regenerating commit message: {}", err),
+        }
+        if !is_conventional(&generation.message) {
+            log::warn!("generated message does not follow Conventional Commits");
+        }
+    }
+
+    if checks.emoji {
+        generation.message = gitmoji::to_emoji(&generation.message);
+    }
+
+    // subject 超长时提示模型缩短重试一次, 还是超长就在单词边界截断
+    let too_long = |message: &str| checks.max_subject_length.filter(|&max_len| subject_len(message) > max_len);
+    if let Some(max_len) = too_long(&generation.message) {
+        if !offline {
+            log::warn!("subject is longer than {} characters, retrying...", max_len);
+            opts.system_prompt = format!("{}\nKeep the subject under {} characters.", opts.system_prompt, max_len);
+            match provider.generate(diff, opts).await {
+                Ok(retry) => {
+                    replace(&mut generation, retry);
+                    if checks.emoji {
+                        generation.message = gitmoji::to_emoji(&generation.message);
+                    }
+                }
+                Err(err) => log::warn!("error regenerating commit message: {}", err),
+            }
+        }
+        if too_long(&generation.message).is_some() {
+            log::warn!("subject is still longer than {} characters, truncating it", max_len);
+            generation.message = truncate_subject(&generation.message, max_len);
+        }
+    }
+    Ok(generation)
+}
+
+// 重试得到的消息代替原来的, 用量累加
+fn replace(generation: &mut Generation, retry: Generation) {
+    generation.message = retry.message;
+    add_usage(&mut generation.usage, retry.usage);
+    generation.truncated = retry.truncated;
+}
@@ -0,0 +1,141 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use super::{
+    check_reply, endpoint_url, user_messages, GenerateOptions, Generation, HttpOptions, Message, Provider, Transport,
+};
+use crate::error::GmhError;
+
+pub const DEFAULT_MODEL: &str = "claude-3-5-haiku-latest";
+pub const API_KEY_VARS: &[&str] = &["ANTHROPIC_API_KEY"];
+const DEFAULT_API_BASE: &str = "https://api.anthropic.com/v1";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+// Anthropic 要求必须传 max_tokens
+const DEFAULT_MAX_TOKENS: u32 = 1024;
+
+#[derive(Serialize, Debug)]
+struct AnthropicRequest {
+    model: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    system: String,
+    messages: Vec<Message>,
+    max_tokens: u32,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AnthropicResponse {
+    content: Vec<ContentBlock>,
+    usage: Option<Usage>,
+    stop_reason: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Usage {
+    input_tokens: u32,
+    output_tokens: u32,
+    #[serde(default)]
+    cache_read_input_tokens: u32,
+}
+
+impl From<Usage> for super::Usage {
+    fn from(usage: Usage) -> Self {
+        // Anthropic 的 input_tokens 不包含缓存命中的部分
+        let prompt_tokens = usage.input_tokens + usage.cache_read_input_tokens;
+        super::Usage {
+            prompt_tokens,
+            completion_tokens: usage.output_tokens,
+            total_tokens: prompt_tokens + usage.output_tokens,
+            cached_tokens: usage.cache_read_input_tokens,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ContentBlock {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: String,
+}
+
+// stream = true 时只关心 content_block_delta 事件
+#[derive(Deserialize, Debug)]
+struct StreamEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    delta: Option<StreamDelta>,
+}
+
+#[derive(Deserialize, Debug)]
+struct StreamDelta {
+    text: Option<String>,
+}
+
+pub struct Anthropic {
+    transport: Transport,
+}
+
+impl Anthropic {
+    pub fn new(api_base: Option<&str>, http: HttpOptions) -> Result<Self, GmhError> {
+        let url = endpoint_url(api_base.unwrap_or(DEFAULT_API_BASE), "/messages")?;
+        Ok(Anthropic {
+            transport: Transport::new(url, http)?,
+        })
+    }
+}
+
+#[async_trait]
+impl Provider for Anthropic {
+    async fn generate(&self, diff: &str, opts: &GenerateOptions) -> Result<Generation, GmhError> {
+        let api_key = self.transport.api_key(API_KEY_VARS)?;
+        if opts.seed.is_some() {
+            log::warn!("anthropic does not support a seed, the output may differ between runs");
+        }
+
+        let request_body = AnthropicRequest {
+            model: opts.model.clone(),
+            system: opts.system_prompt.clone(),
+            messages: user_messages(diff, opts),
+            max_tokens: opts.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+            stream: opts.stream,
+            // Anthropic 只接受 0.0 - 1.0
+            temperature: opts.temperature.map(|t| t.min(1.0)),
+        };
+
+        let request = self
+            .transport
+            .post()
+            .header("x-api-key", api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .json(&request_body);
+        let response = self.transport.send(request).await?;
+
+        if opts.stream {
+            let message = self
+                .transport
+                .read_sse(response, opts.on_chunk.as_ref(), |data| {
+                    let event: StreamEvent = serde_json::from_str(data)?;
+                    if event.kind != "content_block_delta" {
+                        return Ok(None);
+                    }
+                    Ok(event.delta.and_then(|d| d.text))
+                })
+                .await?;
+            return Ok(Generation::new(message, None));
+        }
+
+        let response_body: AnthropicResponse = self.transport.json(response).await?;
+
+        let usage = response_body.usage.map(Into::into);
+        let text: String = response_body
+            .content
+            .into_iter()
+            .filter(|block| block.kind == "text")
+            .map(|block| block.text)
+            .collect();
+        let text = check_reply(text, response_body.stop_reason.as_deref())?;
+        Ok(Generation::new(text, usage).truncated(response_body.stop_reason.as_deref()))
+    }
+}
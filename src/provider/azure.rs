@@ -0,0 +1,66 @@
+use async_trait::async_trait;
+use reqwest::Url;
+use std::env;
+
+use super::{endpoint_url, openai, GenerateOptions, Generation, HttpOptions, Provider, Transport};
+use crate::error::GmhError;
+
+// Azure 上 model 就是 deployment 的名字
+pub const DEFAULT_MODEL: &str = "gpt-4o-mini";
+pub const API_KEY_VARS: &[&str] = &["AZURE_OPENAI_KEY"];
+const ENDPOINT_VAR: &str = "AZURE_OPENAI_ENDPOINT";
+const API_VERSION_VAR: &str = "GMH_AZURE_API_VERSION";
+const DEFAULT_API_VERSION: &str = "2024-10-21";
+
+pub struct Azure {
+    transport: Transport,
+    base: Url,
+    api_version: String,
+}
+
+impl Azure {
+    // 地址优先级: api_base > AZURE_OPENAI_ENDPOINT,
+    // api-version 优先级: api_base 里的 ?api-version= > GMH_AZURE_API_VERSION > 2024-10-21
+    pub fn new(api_base: Option<&str>, http: HttpOptions) -> Result<Self, GmhError> {
+        let base = match api_base {
+            Some(base) => base.to_string(),
+            None => env::var(ENDPOINT_VAR).ok().filter(|v| !v.is_empty()).ok_or_else(|| {
+                GmhError::Config(format!(
+                    "the azure provider needs an endpoint; pass --api-base or set {}",
+                    ENDPOINT_VAR
+                ))
+            })?,
+        };
+        let (base, query) = base.split_once('?').unwrap_or((&base, ""));
+        let from_query = query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("api-version="))
+            .map(str::to_string);
+        let base = endpoint_url(base, "")?;
+        let api_version = from_query
+            .or_else(|| env::var(API_VERSION_VAR).ok().filter(|v| !v.is_empty()))
+            .unwrap_or_else(|| DEFAULT_API_VERSION.to_string());
+        Ok(Azure {
+            transport: Transport::new(base.clone(), http)?,
+            base,
+            api_version,
+        })
+    }
+
+    // {base}/openai/deployments/{deployment}/chat/completions?api-version={ver}
+    fn url(&self, deployment: &str) -> Result<Url, GmhError> {
+        let path = format!("/openai/deployments/{}/chat/completions", deployment);
+        let mut url = endpoint_url(self.base.as_str(), &path)?;
+        url.query_pairs_mut().append_pair("api-version", &self.api_version);
+        Ok(url)
+    }
+}
+
+#[async_trait]
+impl Provider for Azure {
+    async fn generate(&self, diff: &str, opts: &GenerateOptions) -> Result<Generation, GmhError> {
+        let api_key = self.transport.api_key(API_KEY_VARS)?;
+        let request = self.transport.post_to(self.url(&opts.model)?).header("api-key", api_key);
+        openai::complete(&self.transport, request, diff, opts).await
+    }
+}
@@ -0,0 +1,35 @@
+use async_trait::async_trait;
+
+use super::{endpoint_url, openai, GenerateOptions, Generation, HttpOptions, Provider, Transport};
+use crate::error::GmhError;
+
+pub const DEFAULT_MODEL: &str = "deepseek-chat";
+// 以前只读 OPENAI_API_KEY, 为了兼容继续支持
+pub const API_KEY_VARS: &[&str] = &["DEEPSEEK_API_KEY", "OPENAI_API_KEY"];
+const DEFAULT_API_BASE: &str = "https://api.deepseek.com";
+
+// 接口和 OpenAI 兼容, 请求和响应的处理都在 openai::complete 里
+pub struct DeepSeek {
+    transport: Transport,
+}
+
+impl DeepSeek {
+    pub fn new(api_base: Option<&str>, http: HttpOptions) -> Result<Self, GmhError> {
+        let url = endpoint_url(api_base.unwrap_or(DEFAULT_API_BASE), "/chat/completions")?;
+        Ok(DeepSeek {
+            transport: Transport::new(url, http)?,
+        })
+    }
+}
+
+#[async_trait]
+impl Provider for DeepSeek {
+    async fn generate(&self, diff: &str, opts: &GenerateOptions) -> Result<Generation, GmhError> {
+        let api_key = self.transport.api_key(API_KEY_VARS)?;
+        let request = self
+            .transport
+            .post()
+            .header("Authorization", format!("Bearer {}", api_key));
+        openai::complete(&self.transport, request, diff, opts).await
+    }
+}
@@ -0,0 +1,997 @@
+mod anthropic;
+mod azure;
+mod deepseek;
+mod ollama;
+mod openai;
+
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, ClientBuilder, NoProxy, Proxy, RequestBuilder, Response, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::error::GmhError;
+use crate::message;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub use anthropic::Anthropic;
+pub use azure::Azure;
+pub use deepseek::DeepSeek;
+pub use ollama::Ollama;
+pub use openai::OpenAi;
+
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+pub const DEFAULT_TIMEOUT: u64 = 30;
+pub const DEFAULT_MAX_WAIT: u64 = 60;
+// commit message 希望尽量稳定
+pub const DEFAULT_TEMPERATURE: f32 = 0.2;
+
+const RETRY_BASE_DELAY_MS: u64 = 500;
+const SECRET_HEADERS: &[&str] = &["authorization", "x-api-key", "api-key"];
+// OpenAI 兼容接口是 stop, Anthropic 是 end_turn / stop_sequence
+const NORMAL_FINISH_REASONS: &[&str] = &["stop", "end_turn", "stop_sequence"];
+// 达到 max_tokens 时的 finish_reason, 由调用方请模型接着写
+const LENGTH_FINISH_REASONS: &[&str] = &["length", "max_tokens"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    Deepseek,
+    Openai,
+    Anthropic,
+    Ollama,
+    Azure,
+}
+
+impl ProviderKind {
+    pub fn default_model(self) -> &'static str {
+        match self {
+            ProviderKind::Deepseek => deepseek::DEFAULT_MODEL,
+            ProviderKind::Openai => openai::DEFAULT_MODEL,
+            ProviderKind::Anthropic => anthropic::DEFAULT_MODEL,
+            ProviderKind::Ollama => ollama::DEFAULT_MODEL,
+            ProviderKind::Azure => azure::DEFAULT_MODEL,
+        }
+    }
+
+    // 本地的 Ollama 不需要 API key; 依次查找, 第一个是推荐使用的, 都没有时再看 GMH_API_KEY
+    pub fn api_key_vars(self) -> Option<&'static [&'static str]> {
+        match self {
+            ProviderKind::Deepseek => Some(deepseek::API_KEY_VARS),
+            ProviderKind::Openai => Some(openai::API_KEY_VARS),
+            ProviderKind::Anthropic => Some(anthropic::API_KEY_VARS),
+            ProviderKind::Ollama => None,
+            ProviderKind::Azure => Some(azure::API_KEY_VARS),
+        }
+    }
+}
+
+// 流式输出时收到的文本片段交给调用方显示, 库本身不往终端写
+pub type OnChunk = Arc<Mutex<dyn FnMut(&str) + Send>>;
+
+// 每次请求的参数, 和具体 provider 无关
+#[derive(Clone)]
+pub struct GenerateOptions {
+    pub model: String,
+    pub system_prompt: String,
+    pub stream: bool,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    // OpenAI 兼容的 API 和 Ollama 支持, 同样的输入尽量得到同样的输出
+    pub seed: Option<u64>,
+    // 支持 `n` 参数的 provider 一次请求返回多个候选
+    pub candidates: u32,
+    // 用户补充的说明, 放在 diff 之后
+    pub context: Vec<String>,
+    // 继续对话时之前的回复和修改要求, 放在最后
+    pub history: Vec<Message>,
+    // stream 为 true 时每收到一段文本调用一次
+    pub on_chunk: Option<OnChunk>,
+}
+
+impl Default for GenerateOptions {
+    fn default() -> Self {
+        GenerateOptions {
+            model: deepseek::DEFAULT_MODEL.to_string(),
+            system_prompt: message::DEFAULT_SYSTEM_PROMPT.to_string(),
+            stream: false,
+            temperature: Some(DEFAULT_TEMPERATURE),
+            max_tokens: None,
+            seed: None,
+            candidates: 1,
+            context: Vec::new(),
+            history: Vec::new(),
+            on_chunk: None,
+        }
+    }
+}
+
+pub struct HttpOptions {
+    pub timeout: u64,
+    pub max_retries: u32,
+    // 429 带 Retry-After 时最多等待的秒数
+    pub max_wait: u64,
+    // 覆盖 HTTPS_PROXY / HTTP_PROXY, 传入 client 时不生效
+    pub proxy: Option<String>,
+    // 传入自己的 Client (代理、测试用的 mock 服务等), None 时新建一个
+    pub client: Option<Client>,
+    // 直接传入 API key (嵌入到其他程序、测试用), None 时从环境变量读取
+    pub api_key: Option<String>,
+    // 额外加在每个请求上的 header, 例如网关要求的 Helicone-Auth
+    pub headers: Vec<(String, String)>,
+    // 把请求/响应格式化成 JSON 写到这些文件里, API key 打码
+    pub dump_request: Option<PathBuf>,
+    pub dump_response: Option<PathBuf>,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        HttpOptions {
+            timeout: DEFAULT_TIMEOUT,
+            max_retries: DEFAULT_MAX_RETRIES,
+            max_wait: DEFAULT_MAX_WAIT,
+            proxy: None,
+            client: None,
+            api_key: None,
+            headers: Vec::new(),
+            dump_request: None,
+            dump_response: None,
+        }
+    }
+}
+
+// 自己读代理相关的环境变量而不是交给 reqwest, 这样 NO_PROXY 的处理是确定的, -v 时也能看到实际用了哪个代理
+#[derive(Debug, Default)]
+pub struct ProxyConfig {
+    pub https: Option<String>,
+    pub http: Option<String>,
+    pub no_proxy: Option<String>,
+}
+
+impl ProxyConfig {
+    // proxy 用于所有请求, 否则分别取 HTTPS_PROXY 和 HTTP_PROXY (大写优先, 也认小写)
+    pub fn from_env(proxy: Option<&str>) -> Self {
+        let var = |name: &str| {
+            env::var(name)
+                .or_else(|_| env::var(name.to_lowercase()))
+                .ok()
+                .filter(|v| !v.trim().is_empty())
+        };
+        let proxy = proxy.map(str::to_string);
+        ProxyConfig {
+            https: proxy.clone().or_else(|| var("HTTPS_PROXY")),
+            http: proxy.or_else(|| var("HTTP_PROXY")),
+            no_proxy: var("NO_PROXY"),
+        }
+    }
+
+    fn apply(&self, mut builder: ClientBuilder) -> Result<ClientBuilder, GmhError> {
+        let no_proxy = self.no_proxy.as_deref().and_then(NoProxy::from_string);
+        let invalid = |url: &str, e: reqwest::Error| GmhError::Config(format!("invalid proxy URL '{}': {}", url, e));
+        // 先关掉 reqwest 自己读环境变量的逻辑
+        builder = builder.no_proxy();
+        if let Some(url) = &self.https {
+            builder = builder.proxy(Proxy::https(url).map_err(|e| invalid(url, e))?.no_proxy(no_proxy.clone()));
+        }
+        if let Some(url) = &self.http {
+            builder = builder.proxy(Proxy::http(url).map_err(|e| invalid(url, e))?.no_proxy(no_proxy));
+        }
+        Ok(builder)
+    }
+}
+
+impl fmt::Display for ProxyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |value: &Option<String>| value.clone().unwrap_or_else(|| "none".to_string());
+        write!(
+            f,
+            "https {}, http {}, no_proxy {}",
+            show(&self.https),
+            show(&self.http),
+            show(&self.no_proxy)
+        )
+    }
+}
+
+// 生成结果, 流式输出时拿不到 usage
+#[derive(Debug)]
+pub struct Generation {
+    pub message: String,
+    pub usage: Option<Usage>,
+    // n > 1 时除第一个以外的其他候选, 各自记着是否被截断, 用量都算在第一个里
+    pub alternatives: Vec<Generation>,
+    // 因为 max_tokens 在中途停下, 流式输出时不知道
+    pub truncated: bool,
+}
+
+impl Generation {
+    pub fn new(message: String, usage: Option<Usage>) -> Self {
+        Generation {
+            message,
+            usage,
+            alternatives: Vec::new(),
+            truncated: false,
+        }
+    }
+
+    pub fn truncated(mut self, finish_reason: Option<&str>) -> Self {
+        self.truncated = finish_reason.is_some_and(|reason| LENGTH_FINISH_REASONS.contains(&reason));
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Usage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub total_tokens: u32,
+    pub cached_tokens: u32,
+}
+
+impl Usage {
+    // 价格单位为每百万 token
+    pub fn cost(&self, prompt_price: f64, completion_price: f64) -> f64 {
+        (f64::from(self.prompt_tokens) * prompt_price + f64::from(self.completion_tokens) * completion_price)
+            / 1_000_000.0
+    }
+}
+
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "tokens: {} prompt ({} cached) + {} completion = {}",
+            self.prompt_tokens, self.cached_tokens, self.completion_tokens, self.total_tokens
+        )
+    }
+}
+
+impl std::ops::AddAssign for Usage {
+    fn add_assign(&mut self, other: Usage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.total_tokens += other.total_tokens;
+        self.cached_tokens += other.cached_tokens;
+    }
+}
+
+#[async_trait]
+pub trait Provider: Send + Sync {
+    async fn generate(&self, diff: &str, opts: &GenerateOptions) -> Result<Generation, GmhError>;
+}
+
+// api_base 为 None 时使用各 provider 的默认地址
+pub fn build(kind: ProviderKind, api_base: Option<&str>, http: HttpOptions) -> Result<Box<dyn Provider>, GmhError> {
+    Ok(match kind {
+        ProviderKind::Deepseek => Box::new(DeepSeek::new(api_base, http)?),
+        ProviderKind::Openai => Box::new(OpenAi::new(api_base, http)?),
+        ProviderKind::Anthropic => Box::new(Anthropic::new(api_base, http)?),
+        ProviderKind::Ollama => Box::new(Ollama::new(api_base, http)?),
+        ProviderKind::Azure => Box::new(Azure::new(api_base, http)?),
+    })
+}
+
+// https://host 和 https://host/ 得到同一个地址
+pub fn endpoint_url(base: &str, path: &str) -> Result<Url, GmhError> {
+    let url = Url::parse(&format!("{}/{}", base.trim_end_matches('/'), path.trim_start_matches('/')))
+        .map_err(|e| GmhError::Config(format!("invalid API base URL '{}': {}", base, e)))?;
+
+    match url.scheme() {
+        "http" | "https" => Ok(url),
+        scheme => Err(GmhError::Config(format!(
+            "invalid API base URL '{}': unsupported scheme '{}'",
+            base, scheme
+        ))),
+    }
+}
+
+// 所有 provider 都可以用的 key, 只在 provider 自己的变量都没有设置时使用
+pub const GENERIC_API_KEY_VAR: &str = "GMH_API_KEY";
+
+// 返回找到 key 的变量名和 key
+pub fn find_api_key(vars: &[&'static str]) -> Option<(&'static str, String)> {
+    lookup_api_key(vars, |var| env::var(var).ok())
+}
+
+pub fn api_key(vars: &[&'static str]) -> Result<String, GmhError> {
+    let (var, key) = find_api_key(vars).ok_or(GmhError::MissingApiKey(vars[0]))?;
+    log::info!("API key from {}", var);
+    Ok(key)
+}
+
+// get 按变量名取值, 测试时不用修改环境变量
+fn lookup_api_key(vars: &[&'static str], get: impl Fn(&str) -> Option<String>) -> Option<(&'static str, String)> {
+    vars.iter()
+        .chain([GENERIC_API_KEY_VAR].iter())
+        .find_map(|&var| get(var).filter(|v| !v.trim().is_empty()).map(|key| (var, key)))
+}
+
+// 被截断或过滤的回复 content 可能是空的, 这种结果不能拿去提交.
+// finish_reason 不是正常结束时提醒消息可能不完整, 长度超限见 Generation::truncated
+pub fn check_reply(content: String, finish_reason: Option<&str>) -> Result<String, GmhError> {
+    let expected = |reason: &&str| NORMAL_FINISH_REASONS.contains(reason) || LENGTH_FINISH_REASONS.contains(reason);
+    if let Some(reason) = finish_reason.filter(|reason| !expected(reason)) {
+        log::warn!("the model stopped with finish_reason '{}', the message may be incomplete", reason);
+    }
+    if content.trim().is_empty() {
+        Err(GmhError::EmptyMessage)
+    } else {
+        Ok(message::normalize_newlines(&content))
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Message {
+    pub role: String,
+    pub content: String,
+}
+
+impl Message {
+    pub fn new(role: &str, content: &str) -> Self {
+        Message {
+            role: role.to_string(),
+            content: content.to_string(),
+        }
+    }
+}
+
+// diff 在前, --context 的说明跟在后面, 作为对 diff 的补充, 最后是之前的对话
+pub fn user_messages(diff: &str, opts: &GenerateOptions) -> Vec<Message> {
+    let mut messages = vec![Message::new("user", diff)];
+    messages.extend(opts.context.iter().map(|hint| Message::new("user", hint)));
+    messages.extend(opts.history.iter().cloned());
+    messages
+}
+
+// system prompt 放在 messages 里的 provider 使用, --template 时 system prompt 为空, 不发送
+pub fn chat_messages(diff: &str, opts: &GenerateOptions) -> Vec<Message> {
+    let mut messages = Vec::new();
+    if !opts.system_prompt.is_empty() {
+        messages.push(Message::new("system", &opts.system_prompt));
+    }
+    messages.extend(user_messages(diff, opts));
+    messages
+}
+
+// 负责超时、重试和 SSE 读取, 各 provider 只关心请求/响应的格式
+pub struct Transport {
+    client: Client,
+    url: Url,
+    timeout: u64,
+    max_retries: u32,
+    max_wait: u64,
+    api_key: Option<String>,
+    headers: Vec<(String, String)>,
+    dump_request: Option<PathBuf>,
+    dump_response: Option<PathBuf>,
+}
+
+impl Transport {
+    pub fn new(url: Url, http: HttpOptions) -> Result<Self, GmhError> {
+        let client = match http.client {
+            Some(client) => client,
+            None => {
+                let proxy = ProxyConfig::from_env(http.proxy.as_deref());
+                log::info!("proxy: {}", proxy);
+                proxy.apply(Client::builder())?.build()?
+            }
+        };
+        Ok(Transport {
+            client,
+            url,
+            timeout: http.timeout,
+            max_retries: http.max_retries,
+            max_wait: http.max_wait,
+            api_key: http.api_key,
+            headers: http.headers,
+            dump_request: http.dump_request,
+            dump_response: http.dump_response,
+        })
+    }
+
+    // 传入的 key 优先, 没有时从 vars 和 GMH_API_KEY 读取
+    pub fn api_key(&self, vars: &[&'static str]) -> Result<String, GmhError> {
+        match &self.api_key {
+            Some(key) => Ok(key.clone()),
+            None => api_key(vars),
+        }
+    }
+
+    pub fn post(&self) -> RequestBuilder {
+        self.post_to(self.url.clone())
+    }
+
+    // 超时设置在每个请求上, 传入的 Client 也同样生效.
+    // 地址随请求变化的 provider (Azure 的 deployment) 用这个
+    pub fn post_to(&self, url: Url) -> RequestBuilder {
+        let request = self
+            .client
+            .post(url)
+            .timeout(Duration::from_secs(self.timeout))
+            .header("Content-Type", "application/json");
+        self.headers
+            .iter()
+            .fold(request, |request, (name, value)| request.header(name, value))
+    }
+
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, GmhError> {
+        if log::log_enabled!(log::Level::Debug) {
+            log_request(&request);
+        }
+        if let Some(path) = &self.dump_request {
+            dump_request(path, &request)?;
+        }
+        let mut attempt = 0;
+        loop {
+            let result = request
+                .try_clone()
+                .expect("JSON request bodies can always be cloned")
+                .send()
+                .await;
+
+            match &result {
+                Ok(response) => log::debug!("HTTP {}", response.status()),
+                Err(err) => log::debug!("request failed: {}", err),
+            }
+            let retryable = match &result {
+                Ok(response) => is_retryable_status(response.status()),
+                Err(err) => err.is_timeout(),
+            };
+            if !retryable || attempt >= self.max_retries {
+                let response = result.map_err(|err| self.error(err))?;
+                // 错误响应的 body 不一定是 JSON, 原样带到错误信息里
+                if !response.status().is_success() {
+                    let status = response.status();
+                    let headers = response.headers().clone();
+                    let body = response.text().await.unwrap_or_default();
+                    if let Some(path) = &self.dump_response {
+                        dump_response(path, status, &headers, &body)?;
+                    }
+                    return Err(GmhError::Api { status, body });
+                }
+                return Ok(response);
+            }
+
+            attempt += 1;
+            log::warn!("retrying ({}/{})...", attempt, self.max_retries);
+            // 服务端给了等待时间就照着等, 否则指数退避
+            let rate_limit = match &result {
+                Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => retry_after(response.headers()),
+                _ => None,
+            };
+            let delay = match rate_limit {
+                Some(wait) => {
+                    let wait = wait.min(Duration::from_secs(self.max_wait));
+                    log::info!("rate limited, waiting {}s", wait.as_secs_f64().ceil());
+                    wait
+                }
+                None => backoff_delay(attempt - 1, jitter()),
+            };
+            log::info!("retry {}/{} in {}ms", attempt, self.max_retries, delay.as_millis());
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    pub async fn json<T: DeserializeOwned>(&self, response: Response) -> Result<T, GmhError> {
+        let Some(path) = &self.dump_response else {
+            return response.json().await.map_err(|err| self.error(err));
+        };
+        let (status, headers) = (response.status(), response.headers().clone());
+        let body = response.text().await.map_err(|err| self.error(err))?;
+        dump_response(path, status, &headers, &body)?;
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    // 解析 server-sent events, 每个片段交给 on_chunk, 最后返回完整消息.
+    // extract 从一个 data: 负载中取出文本片段
+    pub async fn read_sse<F>(
+        &self,
+        response: Response,
+        on_chunk: Option<&OnChunk>,
+        mut extract: F,
+    ) -> Result<String, GmhError>
+    where
+        F: FnMut(&str) -> Result<Option<String>, GmhError> + Send,
+    {
+        self.read_lines(response, on_chunk, |line| {
+            let Some(data) = line.strip_prefix("data:") else {
+                return Ok(Chunk::Skip);
+            };
+            let data = data.trim();
+            if data == "[DONE]" {
+                return Ok(Chunk::Done);
+            }
+            Ok(extract(data)?.map_or(Chunk::Skip, Chunk::Text))
+        })
+        .await
+    }
+
+    // 每行一个 JSON 对象 (Ollama 的流式格式)
+    pub async fn read_ndjson<F>(
+        &self,
+        response: Response,
+        on_chunk: Option<&OnChunk>,
+        mut extract: F,
+    ) -> Result<String, GmhError>
+    where
+        F: FnMut(&str) -> Result<Option<String>, GmhError> + Send,
+    {
+        self.read_lines(response, on_chunk, |line| {
+            if line.is_empty() {
+                return Ok(Chunk::Skip);
+            }
+            Ok(extract(line)?.map_or(Chunk::Skip, Chunk::Text))
+        })
+        .await
+    }
+
+    async fn read_lines<F>(
+        &self,
+        mut response: Response,
+        on_chunk: Option<&OnChunk>,
+        mut parse: F,
+    ) -> Result<String, GmhError>
+    where
+        F: FnMut(&str) -> Result<Chunk, GmhError> + Send,
+    {
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut message = String::new();
+        let (status, headers) = (response.status(), response.headers().clone());
+        // --dump-response 时保存原始的事件流
+        let mut raw = String::new();
+
+        'outer: while let Some(chunk) = response.chunk().await.map_err(|err| self.error(err))? {
+            buffer.extend_from_slice(&chunk);
+
+            while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=pos).collect();
+                let line = String::from_utf8_lossy(&line);
+                if self.dump_response.is_some() {
+                    raw.push_str(&line);
+                }
+
+                match parse(line.trim())? {
+                    Chunk::Text(content) => {
+                        if let Some(on_chunk) = on_chunk {
+                            (on_chunk.lock().unwrap_or_else(|err| err.into_inner()))(&content);
+                        }
+                        message.push_str(&content);
+                    }
+                    Chunk::Skip => {}
+                    Chunk::Done => break 'outer,
+                }
+            }
+        }
+        if let Some(path) = &self.dump_response {
+            dump_response(path, status, &headers, &raw)?;
+        }
+
+        if message.trim().is_empty() {
+            Err(GmhError::EmptyMessage)
+        } else {
+            Ok(message::normalize_newlines(&message))
+        }
+    }
+
+    fn error(&self, err: reqwest::Error) -> GmhError {
+        if err.is_timeout() {
+            GmhError::Timeout(self.timeout)
+        } else {
+            GmhError::Http(err)
+        }
+    }
+}
+
+enum Chunk {
+    Text(String),
+    Skip,
+    Done,
+}
+
+// --header 加的 Helicone-Auth 之类也可能带着 key
+fn header_value<'a>(name: &HeaderName, value: &'a HeaderValue) -> &'a str {
+    if SECRET_HEADERS.contains(&name.as_str()) || name.as_str().contains("auth") {
+        "[redacted]"
+    } else {
+        value.to_str().unwrap_or("[binary]")
+    }
+}
+
+// 记录请求的地址和大小, trace 级别再带上 header, API key 一律打码
+fn log_request(request: &RequestBuilder) {
+    let Some(request) = request.try_clone().and_then(|r| r.build().ok()) else {
+        return;
+    };
+    let size = request.body().and_then(|body| body.as_bytes()).map_or(0, <[u8]>::len);
+    log::debug!("{} {} ({} bytes)", request.method(), request.url(), size);
+    for (name, value) in request.headers() {
+        log::trace!("{}: {}", name, header_value(name, value));
+    }
+}
+
+fn headers_json(headers: &HeaderMap) -> serde_json::Value {
+    headers
+        .iter()
+        .map(|(name, value)| (name.to_string(), serde_json::Value::from(header_value(name, value))))
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+// 不是 JSON 的 body (错误页面、SSE 事件流) 原样作为字符串
+fn body_json(body: &[u8]) -> serde_json::Value {
+    serde_json::from_slice(body).unwrap_or_else(|_| String::from_utf8_lossy(body).into())
+}
+
+fn write_dump(path: &Path, dump: serde_json::Value) -> Result<(), GmhError> {
+    fs::write(path, serde_json::to_string_pretty(&dump)? + "\n").map_err(|source| GmhError::File {
+        path: path.display().to_string(),
+        source,
+    })
+}
+
+fn dump_request(path: &Path, request: &RequestBuilder) -> Result<(), GmhError> {
+    let request = request
+        .try_clone()
+        .expect("JSON request bodies can always be cloned")
+        .build()?;
+    let body = request.body().and_then(|body| body.as_bytes()).unwrap_or_default();
+    write_dump(
+        path,
+        serde_json::json!({
+            "method": request.method().as_str(),
+            "url": request.url().as_str(),
+            "headers": headers_json(request.headers()),
+            "body": body_json(body),
+        }),
+    )
+}
+
+fn dump_response(path: &Path, status: StatusCode, headers: &HeaderMap, body: &str) -> Result<(), GmhError> {
+    write_dump(
+        path,
+        serde_json::json!({
+            "status": status.as_u16(),
+            "headers": headers_json(headers),
+            "body": body_json(body.as_bytes()),
+        }),
+    )
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+// Retry-After 只支持秒数 (HTTP 日期格式很少见), 其次是 OpenAI 的 retry-after-ms
+// 和 x-ratelimit-reset-requests / x-ratelimit-reset 里 "6m0s"、"250ms" 这样的时长
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok()).map(str::trim);
+    if let Some(ms) = header("retry-after-ms").and_then(|v| v.parse::<f64>().ok()) {
+        return secs_to_duration(ms / 1000.0);
+    }
+    if let Some(secs) = header("retry-after").and_then(|v| v.parse::<f64>().ok()) {
+        return secs_to_duration(secs);
+    }
+    ["x-ratelimit-reset-requests", "x-ratelimit-reset-tokens", "x-ratelimit-reset"]
+        .iter()
+        .find_map(|name| header(name).and_then(parse_reset_duration))
+}
+
+// "1s"、"6m0s"、"1h2m3.5s"、"250ms", 也接受纯数字秒数
+fn parse_reset_duration(value: &str) -> Option<Duration> {
+    if let Ok(secs) = value.parse::<f64>() {
+        return secs_to_duration(secs);
+    }
+    let mut total = 0.0;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let number_len = rest.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+        let number: f64 = rest[..number_len].parse().ok()?;
+        rest = &rest[number_len..];
+        let unit_len = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(rest.len());
+        total += number
+            * match &rest[..unit_len] {
+                "h" => 3600.0,
+                "m" => 60.0,
+                "s" => 1.0,
+                "ms" => 0.001,
+                _ => return None,
+            };
+        rest = &rest[unit_len..];
+    }
+    secs_to_duration(total)
+}
+
+// inf、1e30 这样的值 from_secs_f64 会 panic, 当作没有提示; 负数和 NaN 当作 0
+fn secs_to_duration(secs: f64) -> Option<Duration> {
+    Duration::try_from_secs_f64(secs.max(0.0)).ok()
+}
+
+// 第 n 次重试等待 500ms * 2^n, 再加上最多一半的随机抖动 (jitter 取值 [0, 1))
+fn backoff_delay(attempt: u32, jitter: f64) -> Duration {
+    let base = RETRY_BASE_DELAY_MS.saturating_mul(1u64 << attempt.min(16));
+    Duration::from_millis(base + (base as f64 * 0.5 * jitter.clamp(0.0, 1.0)) as u64)
+}
+
+fn jitter() -> f64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    f64::from(nanos % 1000) / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+    use serde_json::json;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    const DEEPSEEK_REPLY: &str = r#"{"id": "1", "object": "chat.completion", "created": 0, "model": "deepseek-chat",
+        "choices": [{"index": 0, "message": {"role": "assistant", "content": "Fix the parser\r\n\nDetails"},
+            "logprobs": null, "finish_reason": "stop"}],
+        "usage": {"prompt_tokens": 10, "completion_tokens": 4, "total_tokens": 14,
+            "prompt_cache_hit_tokens": 2, "prompt_cache_miss_tokens": 8},
+        "system_fingerprint": "fp"}"#;
+
+    // 本地的 mock 服务, 按顺序回复 replies (用完后重复最后一个), 记下收到的每个请求的 body
+    async fn mock_server(replies: Vec<(u16, &'static str)>) -> (String, Arc<Mutex<Vec<serde_json::Value>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+        tokio::spawn(async move {
+            for i in 0.. {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let body = read_body(&mut socket).await;
+                received.lock().unwrap().push(serde_json::from_slice(&body).unwrap());
+                let (status, reply) = replies.get(i).or(replies.last()).unwrap();
+                let response = format!(
+                    "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    reply.len(),
+                    reply
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (base, requests)
+    }
+
+    // 读完请求头, 再按 Content-Length 读 body
+    async fn read_body(socket: &mut TcpStream) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut buf = [0; 4096];
+        loop {
+            let n = socket.read(&mut buf).await.unwrap();
+            assert!(n > 0, "connection closed before the request was complete");
+            data.extend_from_slice(&buf[..n]);
+            let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") else {
+                continue;
+            };
+            let head = String::from_utf8_lossy(&data[..end]).to_lowercase();
+            let len: usize = head
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length:"))
+                .map_or(0, |len| len.trim().parse().unwrap());
+            if data.len() >= end + 4 + len {
+                return data[end + 4..end + 4 + len].to_vec();
+            }
+        }
+    }
+
+    fn mock_http() -> HttpOptions {
+        HttpOptions {
+            client: Some(Client::new()),
+            api_key: Some("test-key".to_string()),
+            ..HttpOptions::default()
+        }
+    }
+
+    fn headers(name: &'static str, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn backoff_doubles_with_each_attempt_and_adds_up_to_half_as_jitter() {
+        assert_eq!(backoff_delay(0, 0.0), Duration::from_millis(500));
+        assert_eq!(backoff_delay(1, 0.0), Duration::from_millis(1000));
+        assert_eq!(backoff_delay(3, 0.0), Duration::from_millis(4000));
+        assert_eq!(backoff_delay(1, 0.5), Duration::from_millis(1250));
+        // jitter 超出范围时按 [0, 1] 处理
+        assert_eq!(backoff_delay(0, 7.0), Duration::from_millis(750));
+        assert_eq!(backoff_delay(0, -1.0), Duration::from_millis(500));
+        // 次数很大时不溢出
+        assert_eq!(backoff_delay(40, 0.0), backoff_delay(16, 0.0));
+    }
+
+    #[test]
+    fn retry_after_reads_seconds_and_durations() {
+        assert_eq!(retry_after(&headers("retry-after", "2")), Some(Duration::from_secs(2)));
+        assert_eq!(retry_after(&headers("retry-after-ms", "250")), Some(Duration::from_millis(250)));
+        assert_eq!(retry_after(&headers("x-ratelimit-reset", "1m30s")), Some(Duration::from_secs(90)));
+        assert_eq!(retry_after(&headers("retry-after", "-5")), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn retry_after_ignores_values_too_large_for_a_duration() {
+        for value in ["inf", "1e30", "99999999999999999999"] {
+            assert_eq!(retry_after(&headers("retry-after", value)), None, "{}", value);
+        }
+        assert_eq!(retry_after(&headers("retry-after-ms", "inf")), None);
+        assert_eq!(retry_after(&headers("retry-after-ms", "1e30")), None);
+        assert_eq!(parse_reset_duration("99999999999999999999h"), None);
+    }
+
+    #[test]
+    fn api_key_prefers_the_provider_variable_and_skips_blank_values() {
+        let vars = &["DEEPSEEK_API_KEY", "OPENAI_API_KEY"];
+        let set = |values: &'static [(&'static str, &'static str)]| {
+            move |var: &str| values.iter().find(|(name, _)| *name == var).map(|(_, value)| value.to_string())
+        };
+        assert_eq!(lookup_api_key(vars, set(&[])), None);
+        assert_eq!(
+            lookup_api_key(vars, set(&[("OPENAI_API_KEY", "old"), ("GMH_API_KEY", "generic")])),
+            Some(("OPENAI_API_KEY", "old".to_string()))
+        );
+        assert_eq!(
+            lookup_api_key(vars, set(&[("DEEPSEEK_API_KEY", " "), ("GMH_API_KEY", "generic")])),
+            Some(("GMH_API_KEY", "generic".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn error_status_returns_the_body_without_retrying() {
+        let (base, requests) = mock_server(vec![(400, r#"{"error": "bad request"}"#)]).await;
+        let http = HttpOptions {
+            max_retries: 2,
+            ..mock_http()
+        };
+        let transport = Transport::new(Url::parse(&base).unwrap(), http).unwrap();
+
+        let err = transport.send(transport.post().body("{}")).await.unwrap_err();
+
+        match &err {
+            GmhError::Api { status, body } => {
+                assert_eq!(*status, StatusCode::BAD_REQUEST);
+                assert!(body.contains("bad request"), "{}", body);
+            }
+            err => panic!("expected an API error, got {:?}", err),
+        }
+        assert_eq!(err.exit_code(), 4);
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn context_hints_follow_the_diff_in_the_request() {
+        let opts = GenerateOptions {
+            system_prompt: "Write a commit message.".to_string(),
+            context: vec!["This fixes the login timeout".to_string()],
+            ..GenerateOptions::default()
+        };
+
+        let messages = serde_json::to_value(chat_messages("diff --git a/x b/x", &opts)).unwrap();
+
+        assert_eq!(
+            messages,
+            json!([
+                {"role": "system", "content": "Write a commit message."},
+                {"role": "user", "content": "diff --git a/x b/x"},
+                {"role": "user", "content": "This fixes the login timeout"},
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn injected_client_reaches_the_mock_server() {
+        let reply = r#"{"message": {"role": "assistant", "content": "Fix the parser"}, "done": true,
+            "prompt_eval_count": 10, "eval_count": 4}"#;
+        let (base, requests) = mock_server(vec![(200, reply)]).await;
+        let provider = build(ProviderKind::Ollama, Some(&base), mock_http()).unwrap();
+        let opts = GenerateOptions {
+            model: "llama3.2".to_string(),
+            system_prompt: "Write a commit message.".to_string(),
+            temperature: Some(0.5),
+            ..GenerateOptions::default()
+        };
+
+        let generation = provider.generate("diff --git a/x b/x", &opts).await.unwrap();
+
+        assert_eq!(
+            requests.lock().unwrap().as_slice(),
+            [json!({
+                "model": "llama3.2",
+                "messages": [
+                    {"role": "system", "content": "Write a commit message."},
+                    {"role": "user", "content": "diff --git a/x b/x"},
+                ],
+                "stream": false,
+                "options": {"temperature": 0.5},
+            })]
+        );
+        assert_eq!(generation.message, "Fix the parser");
+        let usage = generation.usage.unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (10, 4));
+    }
+
+    #[tokio::test]
+    async fn deepseek_sends_the_chat_request_and_reads_the_reply() {
+        let (base, requests) = mock_server(vec![(200, DEEPSEEK_REPLY)]).await;
+        let provider = build(ProviderKind::Deepseek, Some(&base), mock_http()).unwrap();
+        let opts = GenerateOptions {
+            system_prompt: "Write a commit message.".to_string(),
+            temperature: Some(0.5),
+            ..GenerateOptions::default()
+        };
+
+        let generation = provider.generate("diff --git a/x b/x", &opts).await.unwrap();
+
+        assert_eq!(
+            requests.lock().unwrap().as_slice(),
+            [json!({
+                "model": "deepseek-chat",
+                "messages": [
+                    {"role": "system", "content": "Write a commit message."},
+                    {"role": "user", "content": "diff --git a/x b/x"},
+                ],
+                "stream": false,
+                "temperature": 0.5,
+            })]
+        );
+        assert_eq!(generation.message, "Fix the parser\n\nDetails");
+        assert!(!generation.truncated);
+        let usage = generation.usage.unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens, usage.cached_tokens), (10, 4, 2));
+    }
+
+    #[tokio::test]
+    async fn each_candidate_knows_whether_it_was_cut_off() {
+        let reply = r#"{"id": "1", "object": "chat.completion", "created": 0, "model": "deepseek-chat",
+            "choices": [
+                {"index": 0, "message": {"role": "assistant", "content": "Fix the parser"},
+                    "logprobs": null, "finish_reason": "stop"},
+                {"index": 1, "message": {"role": "assistant", "content": "Fix the"},
+                    "logprobs": null, "finish_reason": "length"}],
+            "usage": {"prompt_tokens": 10, "completion_tokens": 6, "total_tokens": 16,
+                "prompt_cache_hit_tokens": 0, "prompt_cache_miss_tokens": 10},
+            "system_fingerprint": "fp"}"#;
+        let (base, _) = mock_server(vec![(200, reply)]).await;
+        let provider = build(ProviderKind::Deepseek, Some(&base), mock_http()).unwrap();
+        let opts = GenerateOptions {
+            candidates: 2,
+            ..GenerateOptions::default()
+        };
+
+        let generation = provider.generate("diff", &opts).await.unwrap();
+
+        assert!(!generation.truncated);
+        let alternative = &generation.alternatives[0];
+        assert_eq!(alternative.message, "Fix the");
+        assert!(alternative.truncated);
+    }
+
+    #[tokio::test]
+    async fn unauthorized_is_returned_without_retrying() {
+        let (base, requests) = mock_server(vec![(401, r#"{"error": "bad key"}"#)]).await;
+        let provider = build(ProviderKind::Deepseek, Some(&base), mock_http()).unwrap();
+
+        let err = provider.generate("diff", &GenerateOptions::default()).await.unwrap_err();
+
+        match err {
+            GmhError::Api { status, body } => {
+                assert_eq!(status, StatusCode::UNAUTHORIZED);
+                assert!(body.contains("bad key"), "{}", body);
+            }
+            err => panic!("expected an API error, got {:?}", err),
+        }
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,125 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::env;
+
+use super::{
+    chat_messages, check_reply, endpoint_url, GenerateOptions, Generation, HttpOptions, Message, Provider, Transport,
+    Usage,
+};
+use crate::error::GmhError;
+
+pub const DEFAULT_MODEL: &str = "llama3.2";
+const DEFAULT_HOST: &str = "http://localhost:11434";
+
+#[derive(Serialize, Debug)]
+struct OllamaRequest {
+    model: String,
+    messages: Vec<Message>,
+    stream: bool,
+    options: OllamaOptions,
+}
+
+#[derive(Serialize, Debug)]
+struct OllamaOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+}
+
+// 非流式返回一个对象, 流式时每行一个同样结构的对象
+#[derive(Deserialize, Debug)]
+struct OllamaResponse {
+    message: Option<MessageResponse>,
+    prompt_eval_count: Option<u32>,
+    eval_count: Option<u32>,
+    done_reason: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MessageResponse {
+    content: String,
+}
+
+pub struct Ollama {
+    transport: Transport,
+}
+
+impl Ollama {
+    // 地址优先级: api_base > GMH_OLLAMA_HOST > localhost:11434
+    pub fn new(api_base: Option<&str>, http: HttpOptions) -> Result<Self, GmhError> {
+        let host = match api_base {
+            Some(base) => base.to_string(),
+            None => env::var("GMH_OLLAMA_HOST")
+                .ok()
+                .filter(|v| !v.is_empty())
+                .map(|host| with_scheme(&host))
+                .unwrap_or_else(|| DEFAULT_HOST.to_string()),
+        };
+        let url = endpoint_url(&host, "/api/chat")?;
+        Ok(Ollama {
+            transport: Transport::new(url, http)?,
+        })
+    }
+}
+
+// 和 OLLAMA_HOST 一样允许省略 http://
+fn with_scheme(host: &str) -> String {
+    if host.contains("://") {
+        host.to_string()
+    } else {
+        format!("http://{}", host)
+    }
+}
+
+#[async_trait]
+impl Provider for Ollama {
+    // 本地模型不需要 API key
+    async fn generate(&self, diff: &str, opts: &GenerateOptions) -> Result<Generation, GmhError> {
+        let request_body = OllamaRequest {
+            model: opts.model.clone(),
+            messages: chat_messages(diff, opts),
+            stream: opts.stream,
+            options: OllamaOptions {
+                temperature: opts.temperature,
+                num_predict: opts.max_tokens,
+                seed: opts.seed,
+            },
+        };
+
+        let request = self.transport.post().json(&request_body);
+        let response = self.transport.send(request).await?;
+
+        if opts.stream {
+            let message = self
+                .transport
+                .read_ndjson(response, opts.on_chunk.as_ref(), |line| {
+                    let chunk: OllamaResponse = serde_json::from_str(line)?;
+                    Ok(chunk.message.map(|m| m.content).filter(|c| !c.is_empty()))
+                })
+                .await?;
+            return Ok(Generation::new(message, None));
+        }
+
+        let response_body: OllamaResponse = self.transport.json(response).await?;
+
+        let usage = match (response_body.prompt_eval_count, response_body.eval_count) {
+            (Some(prompt), Some(completion)) => Some(Usage {
+                prompt_tokens: prompt,
+                completion_tokens: completion,
+                total_tokens: prompt + completion,
+                cached_tokens: 0,
+            }),
+            _ => None,
+        };
+        match response_body.message {
+            Some(message) => {
+                let message = check_reply(message.content, response_body.done_reason.as_deref())?;
+                Ok(Generation::new(message, usage).truncated(response_body.done_reason.as_deref()))
+            }
+            None => Err(GmhError::NoChoices),
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use async_trait::async_trait;
+use reqwest::RequestBuilder;
+use serde::{Deserialize, Serialize};
+use super::{
+    chat_messages, check_reply, endpoint_url, GenerateOptions, Generation, HttpOptions, Message, Provider, Transport,
+};
+use crate::error::GmhError;
+
+pub const DEFAULT_MODEL: &str = "gpt-4o-mini";
+pub const API_KEY_VARS: &[&str] = &["OPENAI_API_KEY"];
+const DEFAULT_API_BASE: &str = "https://api.openai.com/v1";
+
+#[derive(Serialize, Debug)]
+struct OpenAiRequest {
+    model: String,
+    messages: Vec<Message>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "is_one")]
+    n: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+}
+
+fn is_one(n: &u32) -> bool {
+    *n == 1
+}
+
+#[derive(Deserialize, Debug)]
+struct OpenAiResponse {
+    choices: Vec<Choice>,
+    usage: Option<Usage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Usage {
+    prompt_tokens: u32,
+    completion_tokens: u32,
+    total_tokens: u32,
+    prompt_tokens_details: Option<PromptTokensDetails>,
+    // DeepSeek 用这个字段报告命中缓存的 token
+    prompt_cache_hit_tokens: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PromptTokensDetails {
+    #[serde(default)]
+    cached_tokens: u32,
+}
+
+impl From<Usage> for super::Usage {
+    fn from(usage: Usage) -> Self {
+        super::Usage {
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            total_tokens: usage.total_tokens,
+            cached_tokens: usage
+                .prompt_tokens_details
+                .map(|d| d.cached_tokens)
+                .or(usage.prompt_cache_hit_tokens)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Choice {
+    message: MessageResponse,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MessageResponse {
+    content: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OpenAiStreamChunk {
+    choices: Vec<StreamChoice>,
+}
+
+#[derive(Deserialize, Debug)]
+struct StreamChoice {
+    delta: Delta,
+}
+
+#[derive(Deserialize, Debug)]
+struct Delta {
+    content: Option<String>,
+}
+
+pub struct OpenAi {
+    transport: Transport,
+}
+
+impl OpenAi {
+    pub fn new(api_base: Option<&str>, http: HttpOptions) -> Result<Self, GmhError> {
+        let url = endpoint_url(api_base.unwrap_or(DEFAULT_API_BASE), "/chat/completions")?;
+        Ok(OpenAi {
+            transport: Transport::new(url, http)?,
+        })
+    }
+}
+
+#[async_trait]
+impl Provider for OpenAi {
+    async fn generate(&self, diff: &str, opts: &GenerateOptions) -> Result<Generation, GmhError> {
+        let api_key = self.transport.api_key(API_KEY_VARS)?;
+        let request = self
+            .transport
+            .post()
+            .header("Authorization", format!("Bearer {}", api_key));
+        complete(&self.transport, request, diff, opts).await
+    }
+}
+
+// DeepSeek 和 Azure OpenAI 的请求和响应格式一样, 只是地址和认证方式不同, request 里已经带好了认证的 header
+pub(super) async fn complete(
+    transport: &Transport,
+    request: RequestBuilder,
+    diff: &str,
+    opts: &GenerateOptions,
+) -> Result<Generation, GmhError> {
+    let request_body = OpenAiRequest {
+        model: opts.model.clone(),
+        messages: chat_messages(diff, opts),
+        stream: opts.stream,
+        temperature: opts.temperature,
+        max_tokens: opts.max_tokens,
+        n: opts.candidates.max(1),
+        seed: opts.seed,
+    };
+    let response = transport.send(request.json(&request_body)).await?;
+
+    if opts.stream {
+        let message = transport
+            .read_sse(response, opts.on_chunk.as_ref(), |data| {
+                let chunk: OpenAiStreamChunk = serde_json::from_str(data)?;
+                Ok(chunk.choices.into_iter().filter_map(|c| c.delta.content).reduce(|a, b| a + &b))
+            })
+            .await?;
+        return Ok(Generation::new(message, None));
+    }
+
+    let response_body: OpenAiResponse = transport.json(response).await?;
+
+    let usage = response_body.usage.map(Into::into);
+    let mut choices = response_body.choices.into_iter();
+    match choices.next() {
+        Some(choice) => {
+            let message = check_reply(choice.message.content.unwrap_or_default(), choice.finish_reason.as_deref())?;
+            let mut generation = Generation::new(message, usage).truncated(choice.finish_reason.as_deref());
+            generation.alternatives = choices
+                .filter_map(|choice| {
+                    let content = choice.message.content.filter(|content| !content.trim().is_empty())?;
+                    Some(Generation::new(content, None).truncated(choice.finish_reason.as_deref()))
+                })
+                .collect();
+            Ok(generation)
+        }
+        None => Err(GmhError::NoChoices),
+    }
+}
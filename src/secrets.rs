@@ -0,0 +1,101 @@
+use regex::Regex;
+
+use crate::diff;
+
+// (名称, 正则), 只检查新增的行. token= 这类通用规则匹配到的值还要再看熵, 避免把占位符当成密钥
+pub const SECRET_PATTERNS: &[(&str, &str)] = &[
+    ("AWS access key", r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b"),
+    ("private key", r"-----BEGIN (?:[A-Z]+ )?PRIVATE KEY( BLOCK)?-----"),
+    ("GitHub token", r"\bgh[pousr]_[A-Za-z0-9]{36,}\b"),
+    ("Slack token", r"\bxox[abpors]-[A-Za-z0-9-]{10,}"),
+    ("OpenAI-style API key", r"\bsk-[A-Za-z0-9_-]{20,}"),
+    ("Google API key", r"\bAIza[0-9A-Za-z_-]{35}\b"),
+];
+
+// key = value 形式的赋值, 值要足够长且熵足够高
+const ASSIGNMENT_PATTERN: &str =
+    r#"(?i)(?:token|secret|passw(?:or)?d|api[_-]?key|access[_-]?key|auth)\w*["']?\s*[:=]\s*["']?([A-Za-z0-9_\-/+=.]{20,})"#;
+const MIN_ENTROPY: f64 = 3.5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretMatch {
+    pub path: String,
+    // 新文件里的行号
+    pub line: usize,
+    pub kind: &'static str,
+}
+
+// 每个字符的 Shannon 熵 (bit)
+fn entropy(value: &str) -> f64 {
+    let len = value.chars().count() as f64;
+    let mut counts: Vec<(char, usize)> = Vec::new();
+    for c in value.chars() {
+        match counts.iter_mut().find(|(k, _)| *k == c) {
+            Some((_, n)) => *n += 1,
+            None => counts.push((c, 1)),
+        }
+    }
+    counts
+        .iter()
+        .map(|(_, n)| {
+            let p = *n as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+// `@@ -a,b +c,d @@` 里新文件的起始行号
+fn hunk_start(header: &str) -> Option<usize> {
+    let new = header.split_whitespace().find(|part| part.starts_with('+'))?;
+    new[1..].split(',').next()?.parse().ok()
+}
+
+// 扫描 diff 里新增的行, 每行最多报告一次
+pub fn scan(diff: &str) -> Vec<SecretMatch> {
+    let patterns: Vec<(&'static str, Regex)> = SECRET_PATTERNS
+        .iter()
+        .map(|(kind, pattern)| (*kind, Regex::new(pattern).expect("invalid secret pattern")))
+        .collect();
+    let assignment = Regex::new(ASSIGNMENT_PATTERN).expect("invalid secret pattern");
+
+    let mut matches = Vec::new();
+    for section in diff::split_files(diff) {
+        let path = diff::file_path(section).unwrap_or("(unknown file)");
+        let mut line_no = None;
+        for line in section.lines() {
+            if line.starts_with("@@") {
+                line_no = hunk_start(line);
+                continue;
+            }
+            let Some(current) = line_no else {
+                continue;
+            };
+            // 删除的行和 `\ No newline at end of file` 不占新文件的行号
+            if line.starts_with('-') || line.starts_with('\\') {
+                continue;
+            }
+            line_no = Some(current + 1);
+            let Some(added) = line.strip_prefix('+') else {
+                continue;
+            };
+            let kind = patterns
+                .iter()
+                .find(|(_, re)| re.is_match(added))
+                .map(|(kind, _)| *kind)
+                .or_else(|| {
+                    assignment
+                        .captures(added)
+                        .filter(|caps| entropy(&caps[1]) >= MIN_ENTROPY)
+                        .map(|_| "high-entropy secret assignment")
+                });
+            if let Some(kind) = kind {
+                matches.push(SecretMatch {
+                    path: path.to_string(),
+                    line: current,
+                    kind,
+                });
+            }
+        }
+    }
+    matches
+}
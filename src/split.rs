@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::GmhError;
+
+pub const SYSTEM_PROMPT_HINT: &str = "Instead of a single message, group the changed files into logically separate commits. Reply with only a JSON array of objects like [{\"files\": [\"path/a\", \"path/b\"], \"message\": \"commit message\"}]. Use the file paths exactly as they appear in the diff and put every file in exactly one group.";
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct SplitCommit {
+    pub files: Vec<String>,
+    pub message: String,
+}
+
+// 模型可能在 JSON 外面包一层 ```json 或者说明文字, 只取第一个 [ 到最后一个 ] 之间的内容.
+// 不在 diff 里的文件和重复出现的文件会被丢掉
+pub fn parse(reply: &str, diff_files: &[&str]) -> Result<Vec<SplitCommit>, GmhError> {
+    let json = match (reply.find('['), reply.rfind(']')) {
+        (Some(start), Some(end)) if start < end => &reply[start..=end],
+        _ => return Err(GmhError::InvalidSplit("no JSON array in the reply".to_string())),
+    };
+    let proposed: Vec<SplitCommit> =
+        serde_json::from_str(json).map_err(|err| GmhError::InvalidSplit(err.to_string()))?;
+
+    let mut seen: Vec<String> = Vec::new();
+    let mut commits = Vec::new();
+    for commit in proposed {
+        let mut files = Vec::new();
+        for file in commit.files {
+            if !diff_files.contains(&file.as_str()) {
+                log::warn!("ignoring '{}', it is not in the diff", file);
+            } else if !seen.contains(&file) {
+                seen.push(file.clone());
+                files.push(file);
+            }
+        }
+        let message = commit.message.trim().to_string();
+        if !files.is_empty() && !message.is_empty() {
+            commits.push(SplitCommit { files, message });
+        }
+    }
+
+    if commits.is_empty() {
+        return Err(GmhError::InvalidSplit("no usable commits in the reply".to_string()));
+    }
+    let missing: Vec<&str> = diff_files
+        .iter()
+        .copied()
+        .filter(|file| !seen.iter().any(|s| s == file))
+        .collect();
+    if !missing.is_empty() {
+        log::warn!("not assigned to any commit, left staged: {}", missing.join(", "));
+    }
+    Ok(commits)
+}
@@ -0,0 +1,183 @@
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Position};
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+use gmh::GmhError;
+
+// stat 最多占的行数, 剩下的留给消息
+const MAX_STAT_HEIGHT: u16 = 12;
+
+pub enum Action {
+    Accept(String),
+    Regenerate,
+}
+
+// 显示 diff stat 和消息, a 提交, r 重新生成, e 编辑, q 取消.
+// 重新生成由调用方完成, 生成期间终端恢复原样, 可以看到 spinner
+pub fn review(stat: &str, message: &str) -> Result<Action, GmhError> {
+    let mut terminal = ratatui::try_init()?;
+    let result = run(&mut terminal, stat, message);
+    ratatui::restore();
+    result
+}
+
+fn run(terminal: &mut DefaultTerminal, stat: &str, message: &str) -> Result<Action, GmhError> {
+    let mut editor = Editor::new(message);
+    loop {
+        terminal.draw(|frame| draw(frame, stat, &editor))?;
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        // raw mode 下 Ctrl-C 不会产生信号
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Err(GmhError::Canceled("Commit canceled."));
+        }
+        if editor.editing {
+            editor.input(key);
+            continue;
+        }
+        match key.code {
+            KeyCode::Char('a') => return Ok(Action::Accept(editor.text())),
+            KeyCode::Char('r') => return Ok(Action::Regenerate),
+            KeyCode::Char('e') => editor.editing = true,
+            KeyCode::Char('q') | KeyCode::Esc => return Err(GmhError::Canceled("Commit canceled.")),
+            _ => {}
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, stat: &str, editor: &Editor) {
+    let stat_height = (stat.lines().count() as u16 + 2).min(MAX_STAT_HEIGHT);
+    let [stat_area, message_area, help_area] = Layout::vertical([
+        Constraint::Length(stat_height),
+        Constraint::Min(3),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    frame.render_widget(Paragraph::new(stat).block(Block::bordered().title(" Changes ")), stat_area);
+
+    // 光标超出可见区域时向下滚动
+    let visible = message_area.height.saturating_sub(2) as usize;
+    let scroll = editor.row.saturating_sub(visible.saturating_sub(1));
+    let border_style = if editor.editing {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default()
+    };
+    let lines: Vec<Line> = editor.lines.iter().map(|line| Line::from(line.as_str())).collect();
+    frame.render_widget(
+        Paragraph::new(lines)
+            .scroll((scroll as u16, 0))
+            .block(Block::bordered().title(" Commit message ").border_style(border_style)),
+        message_area,
+    );
+
+    let help = if editor.editing {
+        "editing: Esc to stop editing"
+    } else {
+        "a: accept  r: regenerate  e: edit  q: cancel"
+    };
+    frame.render_widget(Paragraph::new(help), help_area);
+
+    if editor.editing {
+        let line = &editor.lines[editor.row];
+        let prefix = &line[..byte_index(line, editor.col)];
+        frame.set_cursor_position(Position::new(
+            message_area.x + 1 + Line::from(prefix).width() as u16,
+            message_area.y + 1 + (editor.row - scroll) as u16,
+        ));
+    }
+}
+
+// 多行文本框, col 按字符计数
+struct Editor {
+    lines: Vec<String>,
+    row: usize,
+    col: usize,
+    editing: bool,
+}
+
+impl Editor {
+    fn new(text: &str) -> Self {
+        let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+        if lines.is_empty() {
+            lines.push(String::new());
+        }
+        Editor {
+            lines,
+            row: 0,
+            col: 0,
+            editing: false,
+        }
+    }
+
+    fn text(&self) -> String {
+        self.lines.join("\n").trim().to_string()
+    }
+
+    fn line_len(&self) -> usize {
+        self.lines[self.row].chars().count()
+    }
+
+    fn input(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Esc => self.editing = false,
+            KeyCode::Char(c) => {
+                let at = byte_index(&self.lines[self.row], self.col);
+                self.lines[self.row].insert(at, c);
+                self.col += 1;
+            }
+            KeyCode::Enter => {
+                let at = byte_index(&self.lines[self.row], self.col);
+                let rest = self.lines[self.row].split_off(at);
+                self.lines.insert(self.row + 1, rest);
+                self.row += 1;
+                self.col = 0;
+            }
+            KeyCode::Backspace if self.col > 0 => {
+                self.col -= 1;
+                let at = byte_index(&self.lines[self.row], self.col);
+                self.lines[self.row].remove(at);
+            }
+            // 行首退格时和上一行合并
+            KeyCode::Backspace if self.row > 0 => {
+                let line = self.lines.remove(self.row);
+                self.row -= 1;
+                self.col = self.line_len();
+                self.lines[self.row].push_str(&line);
+            }
+            KeyCode::Delete if self.col < self.line_len() => {
+                let at = byte_index(&self.lines[self.row], self.col);
+                self.lines[self.row].remove(at);
+            }
+            KeyCode::Delete if self.row + 1 < self.lines.len() => {
+                let next = self.lines.remove(self.row + 1);
+                self.lines[self.row].push_str(&next);
+            }
+            KeyCode::Left if self.col > 0 => self.col -= 1,
+            KeyCode::Right if self.col < self.line_len() => self.col += 1,
+            KeyCode::Up if self.row > 0 => {
+                self.row -= 1;
+                self.col = self.col.min(self.line_len());
+            }
+            KeyCode::Down if self.row + 1 < self.lines.len() => {
+                self.row += 1;
+                self.col = self.col.min(self.line_len());
+            }
+            KeyCode::Home => self.col = 0,
+            KeyCode::End => self.col = self.line_len(),
+            _ => {}
+        }
+    }
+}
+
+fn byte_index(line: &str, col: usize) -> usize {
+    line.char_indices().nth(col).map_or(line.len(), |(i, _)| i)
+}
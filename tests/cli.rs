@@ -0,0 +1,199 @@
+// 跑编译好的 gmh, 仓库、HOME 和缓存都在临时目录里, API 换成本地的 mock 服务
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::{Arc, Mutex};
+use std::{env, fs, thread};
+
+// DeepSeek 的响应, 消息内容是 content
+fn reply(content: &str) -> String {
+    serde_json::json!({
+        "id": "1", "object": "chat.completion", "created": 0, "model": "deepseek-chat",
+        "choices": [{"index": 0, "message": {"role": "assistant", "content": content},
+            "logprobs": null, "finish_reason": "stop"}],
+        "usage": {"prompt_tokens": 10, "completion_tokens": 3, "total_tokens": 13,
+            "prompt_cache_hit_tokens": 0, "prompt_cache_miss_tokens": 10},
+        "system_fingerprint": "fp",
+    })
+    .to_string()
+}
+
+fn mock_server() -> (String, Arc<Mutex<Vec<String>>>) {
+    mock_server_replying("Add a greeting")
+}
+
+// 每个请求都回复 content, 记下收到的每个请求的 body
+fn mock_server_replying(content: &str) -> (String, Arc<Mutex<Vec<String>>>) {
+    let reply = reply(content);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let received = requests.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut len = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    len = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; len];
+            reader.read_exact(&mut body).unwrap();
+            received.lock().unwrap().push(String::from_utf8(body).unwrap());
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                reply.len(),
+                reply
+            )
+            .unwrap();
+        }
+    });
+    (base, requests)
+}
+
+fn git(repo: &Path, args: &[&str]) {
+    let status = Command::new("git").current_dir(repo).args(args).status().unwrap();
+    assert!(status.success(), "git {:?} failed", args);
+}
+
+// 临时目录下的 repo 是一个新的仓库, 暂存了 hello.txt
+fn scratch(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("gmh-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let repo = dir.join("repo");
+    fs::create_dir_all(&repo).unwrap();
+    git(&repo, &["init", "-q"]);
+    git(&repo, &["config", "user.name", "Test"]);
+    git(&repo, &["config", "user.email", "test@example.com"]);
+    fs::write(repo.join("hello.txt"), "hello\n").unwrap();
+    git(&repo, &["add", "hello.txt"]);
+    dir
+}
+
+// 不读用户自己的配置、缓存和 GMH_* 环境变量
+fn command(dir: &Path, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_gmh"));
+    command
+        .current_dir(dir.join("repo"))
+        .args(args)
+        .env("HOME", dir)
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .env("XDG_CACHE_HOME", dir.join("cache"))
+        .env("DEEPSEEK_API_KEY", "test-key");
+    for (var, _) in env::vars().filter(|(var, _)| var.starts_with("GMH_")) {
+        command.env_remove(var);
+    }
+    command
+}
+
+fn gmh(dir: &Path, args: &[&str]) -> Output {
+    command(dir, args).output().unwrap()
+}
+
+#[test]
+fn repeated_run_uses_the_cache_instead_of_the_api() {
+    let dir = scratch("cache");
+    let (base, requests) = mock_server();
+
+    let first = gmh(&dir, &["--api-base", &base, "--dry-run"]);
+    let second = gmh(&dir, &["--api-base", &base, "--dry-run"]);
+
+    assert!(first.status.success(), "{}", String::from_utf8_lossy(&first.stderr));
+    assert_eq!(String::from_utf8_lossy(&first.stdout).trim(), "Add a greeting");
+    assert_eq!(first.stdout, second.stdout);
+    assert_eq!(requests.lock().unwrap().len(), 1);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn changed_temperature_misses_the_cache() {
+    let dir = scratch("cache-temperature");
+    let (base, requests) = mock_server();
+
+    let first = gmh(&dir, &["--api-base", &base, "--dry-run"]);
+    let second = gmh(&dir, &["--api-base", &base, "--dry-run", "--temperature", "1.5"]);
+
+    assert!(first.status.success(), "{}", String::from_utf8_lossy(&first.stderr));
+    assert!(second.status.success(), "{}", String::from_utf8_lossy(&second.stderr));
+    assert_eq!(requests.lock().unwrap().len(), 2);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn every_candidate_goes_through_the_checks() {
+    let dir = scratch("candidates");
+    let (base, requests) = mock_server();
+
+    let args = ["--api-base", &base, "--dry-run", "--no-cache", "--candidates", "2", "--max-subject-length", "10"];
+    let output = gmh(&dir, &args);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "[1] Add a\n[2] Add a\n");
+    // 两个候选各请求一次, 各自因为 subject 太长重试一次
+    assert_eq!(requests.lock().unwrap().len(), 4);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn renamed_file_is_sent_as_a_rename_block() {
+    let dir = scratch("rename");
+    let repo = dir.join("repo");
+    fs::write(repo.join("hello.txt"), (1..=50).map(|i| format!("line {}\n", i)).collect::<String>()).unwrap();
+    git(&repo, &["add", "hello.txt"]);
+    git(&repo, &["commit", "-q", "-m", "Add hello"]);
+    git(&repo, &["mv", "hello.txt", "greeting.txt"]);
+    let (base, requests) = mock_server();
+
+    let output = gmh(&dir, &["--api-base", &base, "--dry-run", "--no-cache"]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let request = requests.lock().unwrap()[0].clone();
+    assert!(request.contains(r"rename from hello.txt\nrename to greeting.txt"), "{}", request);
+    assert!(!request.contains("line 1"), "{}", request);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn renamed_and_edited_file_is_split_under_its_new_path() {
+    let dir = scratch("rename-edit");
+    let repo = dir.join("repo");
+    let lines: String = (1..=50).map(|i| format!("line {}\n", i)).collect();
+    fs::write(repo.join("hello.txt"), &lines).unwrap();
+    git(&repo, &["add", "hello.txt"]);
+    git(&repo, &["commit", "-q", "-m", "Add hello"]);
+    git(&repo, &["mv", "hello.txt", "greeting.txt"]);
+    fs::write(repo.join("greeting.txt"), format!("{}line 51\n", lines)).unwrap();
+    git(&repo, &["add", "greeting.txt"]);
+    let (base, _) = mock_server_replying(r#"[{"files": ["greeting.txt"], "message": "Rename hello to greeting"}]"#);
+
+    let output = gmh(&dir, &["--api-base", &base, "--split", "--dry-run", "--no-cache"]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("files: greeting.txt"), "{}", stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("not in the diff"), "{}", stderr);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn missing_git_executable_exits_with_127() {
+    let dir = scratch("nogit");
+
+    // PATH 里只有一个空目录, 找不到 git
+    let output = command(&dir, &["--dry-run"]).env("PATH", dir.join("empty")).output().unwrap();
+
+    assert_eq!(output.status.code(), Some(127));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("git executable not found on PATH"), "{}", stderr);
+    let _ = fs::remove_dir_all(&dir);
+}